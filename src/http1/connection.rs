@@ -1,5 +1,15 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::time::SystemTime;
+
+use super::date::{format_http_date, Clock};
+use super::events::{Event, EventType, Headers, Response};
+use super::framing::{body_framing, Framing};
+use super::readers::{reader_for, Reader};
+use super::recieve_buffer::ReceiveBuffer;
+use super::state::{self, ConnectionState};
+use super::util::{ErrorKind, ProtocolError};
+use super::writers::{body_writer, write_request, BodyWriter, WRITERS};
 
 /// The default maximum size for incomplete events, set to 16 KB.
 pub const DEFAULT_MAX_INCOMPLETE_EVENT_SIZE: usize = 16 * 1024;
@@ -9,6 +19,8 @@ pub const DEFAULT_MAX_INCOMPLETE_EVENT_SIZE: usize = 16 * 1024;
 /// # Example
 /// 
 /// ```rust
+/// use yo::http1::connection::Sentinel;
+///
 /// let state = Sentinel::NeedData;
 /// assert_eq!(state, Sentinel::NeedData);
 /// ```
@@ -23,6 +35,8 @@ pub enum Sentinel {
 /// # Example
 /// 
 /// ```rust
+/// use yo::http1::connection::Role;
+///
 /// let role = Role::Client;
 /// assert_eq!(role, Role::Client);
 /// ```
@@ -32,260 +46,71 @@ pub enum Role {
     Server,
 }
 
-/// Represents various states a connection can be in.
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let state = ConnectionState::Idle;
-/// assert_eq!(state, ConnectionState::Idle);
-/// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ConnectionState {
-    Done,
-    Error,
-    Idle,
-    MightSwitchProtocol,
-    SwitchedProtocol,
-    SendBody,
-}
-
-/// Represents different types of events in the system.
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let event = Event::Data(vec![1, 2, 3]);
-/// assert_eq!(event, Event::Data(vec![1, 2, 3]));
-/// ```
-#[derive(Debug, Clone)]
-pub enum Event {
-    ConnectionClosed,
-    Data(Vec<u8>),
-    EndOfMessage,
-    Request(Request),
-    Response(Response),
-    InformationalResponse(InformationalResponse),
-}
-
-/// Represents an HTTP request with method, headers, and HTTP version.
-/// 
-/// # Fields:
-/// - `method`: HTTP method (e.g., GET, POST).
-/// - `headers`: Headers as key-value pairs.
-/// - `http_version`: HTTP version (e.g., HTTP/1.1).
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let request = Request {
-///     method: b"GET".to_vec(),
-///     headers: HashMap::new(),
-///     http_version: b"HTTP/1.1".to_vec(),
-/// };
-/// ```
-#[derive(Debug, Clone)]
-pub struct Request {
-    pub method: Vec<u8>,
-    pub headers: HashMap<Vec<u8>, Vec<u8>>,
-    pub http_version: Vec<u8>,
-}
-
-/// Represents an HTTP response with status code, headers, reason, and HTTP version.
-/// 
-/// # Fields:
-/// - `status_code`: HTTP status code (e.g., 200, 404).
-/// - `headers`: Response headers as key-value pairs.
-/// - `reason`: Reason phrase for the status code.
-/// - `http_version`: HTTP version (e.g., HTTP/1.1).
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let response = Response {
-///     status_code: 200,
-///     headers: HashMap::new(),
-///     reason: b"OK".to_vec(),
-///     http_version: b"HTTP/1.1".to_vec(),
-/// };
-/// ```
-#[derive(Debug, Clone)]
-pub struct Response {
-    pub status_code: u16,
-    pub headers: HashMap<Vec<u8>, Vec<u8>>,
-    pub reason: Vec<u8>,
-    pub http_version: Vec<u8>,
-}
-
-/// Represents an informational HTTP response with status code, headers, and reason.
-/// 
-/// # Fields:
-/// - `status_code`: HTTP status code (e.g., 100).
-/// - `headers`: Response headers as key-value pairs.
-/// - `reason`: Reason phrase for the status code.
-/// 
+/// The result of [`Connection::next_event`]: either a parsed event, or a sentinel saying why no
+/// event could be produced yet.
+///
 /// # Example
-/// 
+///
 /// ```rust
-/// let info_response = InformationalResponse {
-///     status_code: 100,
-///     headers: HashMap::new(),
-///     reason: b"Continue".to_vec(),
-/// };
+/// use yo::http1::connection::{NextEvent, Sentinel};
+///
+/// let next = NextEvent::Sentinel(Sentinel::NeedData);
+/// assert!(matches!(next, NextEvent::Sentinel(Sentinel::NeedData)));
 /// ```
 #[derive(Debug, Clone)]
-pub struct InformationalResponse {
-    pub status_code: u16,
-    pub headers: HashMap<Vec<u8>, Vec<u8>>,
-    pub reason: Vec<u8>,
-}
-
-/// A buffer for receiving and storing data with efficient extraction operations.
-/// 
-/// # Fields:
-/// - `buffer`: A `VecDeque` holding the buffered data.
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let mut buffer = ReceiveBuffer { buffer: VecDeque::new() };
-/// buffer.buffer.push_back(b'H');
-/// buffer.buffer.push_back(b'E');
-/// assert_eq!(buffer.buffer.len(), 2);
-/// ```
-pub struct ReceiveBuffer {
-    buffer: VecDeque<u8>,
-}
-
-impl ReceiveBuffer {
-    /// Creates a new, empty `ReceiveBuffer`.
-    /// 
-    /// # Example
-    /// 
-    /// ```rust
-    /// let buffer = ReceiveBuffer::new();
-    /// assert!(buffer.is_empty());
-    /// ```
-    pub fn new() -> Self {
-        ReceiveBuffer {
-            buffer: VecDeque::new(),
-        }
-    }
-    /// Adds data to the buffer.
-    /// 
-    /// # Example
-    /// 
-    /// ```rust
-    /// let mut buffer = ReceiveBuffer::new();
-    /// buffer.add_data(&[1, 2, 3]);
-    /// assert_eq!(buffer.len(), 3);
-    /// ```
-    pub fn add_data(&mut self, data: &[u8]) {
-        self.buffer.extend(data);
-    }
-    /// Removes and returns the first byte from the buffer, or `None` if the buffer is empty.
-    /// 
-    /// # Example
-    /// 
-    /// ```rust
-    /// let mut buffer = ReceiveBuffer::new();
-    /// buffer.add_data(&[1, 2, 3]);
-    /// assert_eq!(buffer.pop(), Some(1));
-    /// assert_eq!(buffer.pop(), Some(2));
-    /// assert_eq!(buffer.pop(), Some(3));
-    /// assert_eq!(buffer.pop(), None);
-    /// ```
-    pub fn pop(&mut self) -> Option<u8> {
-        self.buffer.pop_front()
-    }
-    /// Checks if the buffer is empty.
-    /// 
-    /// # Example
-    /// 
-    /// ```rust
-    /// let mut buffer = ReceiveBuffer::new();
-    /// assert!(buffer.is_empty());
-    /// buffer.add_data(&[1, 2]);
-    /// assert!(!buffer.is_empty());
-    /// ```
-    pub fn is_empty(&self) -> bool {
-        self.buffer.is_empty()
-    }
-    /// Returns the number of bytes currently in the buffer.
-    /// 
-    /// # Example
-    /// 
-    /// ```rust
-    /// let mut buffer = ReceiveBuffer::new();
-    /// buffer.add_data(&[1, 2, 3]);
-    /// assert_eq!(buffer.len(), 3);
-    /// ```
-    pub fn len(&self) -> usize {
-        self.buffer.len()
-    }
+pub enum NextEvent {
+    Event(Event),
+    Sentinel(Sentinel),
 }
 
-/// Retrieves a comma-separated list of values from the headers for the given key.
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let mut headers = HashMap::new();
-/// headers.insert(b"key".to_vec(), b"value1,value2,value3".to_vec());
-/// let values = get_comma_header(&headers, b"key");
-/// assert_eq!(values, vec![b"value1".to_vec(), b"value2".to_vec(), b"value3".to_vec()]);
-/// ```
-pub fn get_comma_header(headers: &HashMap<Vec<u8>, Vec<u8>>, key: &[u8]) -> Vec<Vec<u8>> {
-    headers
-        .get(key)
-        .map(|value| value.split(|&b| b == b',').map(|s| s.to_vec()).collect())
-        .unwrap_or_default()
-}
-/// Sets a comma-separated list of values for the given key in the headers.
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let mut headers = HashMap::new();
-/// set_comma_header(&mut headers, b"key", &[b"value1", b"value2", b"value3"]);
-/// assert_eq!(headers.get(b"key"), Some(&b"value1,value2,value3"[..]));
-/// ```
-pub fn set_comma_header(
-    headers: &mut HashMap<Vec<u8>, Vec<u8>>,
-    key: &[u8],
-    values: &[&[u8]],
-) {
-    headers.insert(key.to_vec(), values.join(&b", "[..]).to_vec());
+/// A request a client sent ahead, while the response to an earlier request was still pending.
+///
+/// It can't enter the state machine until the cycle it belongs to starts, so until then we only
+/// remember what the state machine will need to replay it.
+struct PipelinedRequest {
+    keep_alive: bool,
+    complete: bool,
 }
 
 /// Represents a connection with a client or server, managing roles, state, and communication.
-/// 
+///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::connection::{Connection, Role};
+///
 /// let connection = Connection::new(Role::Client, 16 * 1024);
-/// assert_eq!(connection.our_role, Role::Client);
+/// assert_eq!(connection.our_role(), Role::Client);
 /// ```
 pub struct Connection {
     our_role: Role,
     their_role: Role,
     max_incomplete_event_size: usize,
+    allow_obs_fold: bool,
+    send_date: bool,
+    clock: Clock,
     cstate: ConnectionState,
+    reader: Option<Box<dyn Reader>>,
+    writer: Option<Box<dyn BodyWriter>>,
     receive_buffer: ReceiveBuffer,
     receive_buffer_closed: bool,
     client_is_waiting_for_100_continue: bool,
     their_http_version: Option<Vec<u8>>,
-    request_method: Option<Vec<u8>>,
+    request_methods: VecDeque<Vec<u8>>,
+    pipelined: VecDeque<PipelinedRequest>,
+    bytes_sent: usize,
 }
 
 impl Connection {
     /// Creates a new `Connection` with the given role and max incomplete event size.
-    /// 
+    ///
     /// # Example
-    /// 
+    ///
     /// ```rust
+    /// use yo::http1::connection::{Connection, Role};
+    ///
     /// let connection = Connection::new(Role::Client, 16 * 1024);
-    /// assert_eq!(connection.our_role, Role::Client);
+    /// assert_eq!(connection.our_role(), Role::Client);
     /// ```
     pub fn new(our_role: Role, max_incomplete_event_size: usize) -> Self {
         let their_role = match our_role {
@@ -297,133 +122,958 @@ impl Connection {
             our_role,
             their_role,
             max_incomplete_event_size,
-            cstate: ConnectionState::Idle,
+            allow_obs_fold: false,
+            send_date: false,
+            clock: Box::new(SystemTime::now),
+            cstate: ConnectionState::new(),
+            reader: reader_for(role_sentinel(their_role), state::Sentinel::Idle, None, false),
+            writer: None,
             receive_buffer: ReceiveBuffer::new(),
             receive_buffer_closed: false,
             client_is_waiting_for_100_continue: false,
             their_http_version: None,
-            request_method: None,
+            request_methods: VecDeque::new(),
+            pipelined: VecDeque::new(),
+            bytes_sent: 0,
+        }
+    }
+    /// Returns the role we are playing on this connection.
+    pub fn our_role(&self) -> Role {
+        self.our_role
+    }
+    /// Returns the role the peer is playing on this connection.
+    pub fn their_role(&self) -> Role {
+        self.their_role
+    }
+    /// Controls what happens to obsolete line folding (obs-fold) in the heads and trailers the
+    /// peer sends.
+    ///
+    /// By default a folded header line is a protocol error with a 400 status hint. Legacy peers
+    /// that still fold can be accommodated by allowing it, in which case every fold is replaced
+    /// by a single space as RFC 9112 section 5.2 permits.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, NextEvent, Role};
+    ///
+    /// let folded = b"GET / HTTP/1.1\r\nHost: a\r\nX-Note: one\r\n two\r\n\r\n";
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(folded).unwrap();
+    /// assert_eq!(connection.next_event().unwrap_err().error_status_hint(), 400);
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.set_allow_obs_fold(true);
+    /// connection.receive_data(folded).unwrap();
+    /// match connection.next_event().unwrap() {
+    ///     NextEvent::Event(Event::Request(request)) => {
+    ///         assert_eq!(request.headers.get_comma_header(b"x-note"), vec![b"one two".to_vec()]);
+    ///     }
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    ///
+    /// The setting takes effect straight away, even for the trailer of a body already being read:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, NextEvent, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Request(_)))));
+    ///
+    /// connection.set_allow_obs_fold(true);
+    /// connection.receive_data(b"0\r\nX-Note: one\r\n two\r\n\r\n").unwrap();
+    /// match connection.next_event().unwrap() {
+    ///     NextEvent::Event(Event::EndOfMessage(eom)) => {
+    ///         assert_eq!(eom.headers.get_comma_header(b"x-note"), vec![b"one two".to_vec()]);
+    ///     }
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn set_allow_obs_fold(&mut self, allow_obs_fold: bool) {
+        self.allow_obs_fold = allow_obs_fold;
+        // The current reader may be partway through a chunked body, whose trailer is still to come
+        if let Some(reader) = self.reader.as_mut() {
+            reader.set_allow_obs_fold(allow_obs_fold);
         }
     }
+    /// Controls whether `send` adds a `Date` header to final responses that don't carry one,
+    /// as RFC 9110 section 6.6.1 asks of servers with a clock. Off by default; clients never
+    /// send responses, so it does nothing for them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use yo::http1::{Connection, Event, Response, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.set_send_date(true);
+    /// connection.set_clock(|| UNIX_EPOCH + Duration::from_secs(784111777));
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let response = Response::new(200, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "OK", "1.1").unwrap();
+    /// let head = connection.send(Event::Response(response)).unwrap();
+    /// assert!(head.windows(36).any(|w| w == b"Date: Sun, 06 Nov 1994 08:49:37 GMT\r"));
+    /// ```
+    ///
+    /// A `Date` the application set itself is left alone:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, Response, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.set_send_date(true);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let date = (b"date".to_vec(), b"Thu, 01 Jan 1970 00:00:00 GMT".to_vec());
+    /// let response = Response::new(304, vec![date], "Not Modified", "1.1").unwrap();
+    /// let head = connection.send(Event::Response(response)).unwrap();
+    /// assert_eq!(head.windows(5).filter(|w| w.eq_ignore_ascii_case(b"date:")).count(), 1);
+    /// ```
+    pub fn set_send_date(&mut self, send_date: bool) {
+        self.send_date = send_date;
+    }
+    /// Replaces the clock `Date` headers are taken from, which is the system clock by default.
+    /// Tests can pin it to a fixed time.
+    pub fn set_clock(&mut self, clock: impl Fn() -> SystemTime + Send + Sync + 'static) {
+        self.clock = Box::new(clock);
+    }
+    /// Returns our current state in the connection's state machine.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::connection::{Connection, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// assert_eq!(connection.our_state(), Sentinel::SendResponse);
+    /// ```
+    pub fn our_state(&self) -> state::Sentinel {
+        self.cstate.state(role_sentinel(self.our_role))
+    }
+    /// Returns the peer's current state in the connection's state machine.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::connection::{Connection, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// assert_eq!(connection.their_state(), Sentinel::SendBody);
+    /// ```
+    pub fn their_state(&self) -> state::Sentinel {
+        self.cstate.state(role_sentinel(self.their_role))
+    }
+    /// Resets both sides to `Idle` once the current request/response cycle is over, so the
+    /// connection can carry the next one.
+    ///
+    /// Keep-alive is worked out from the messages themselves: an HTTP/1.0 message or a
+    /// `Connection: close` header on either side moves both parties to `MustClose` instead of
+    /// `Done`, and the connection can't be reused.
+    ///
+    /// # Errors
+    /// Returns an `IllegalEvent` error unless both sides are `Done`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, Event, Response, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.next_event().unwrap();
+    /// let response = Response::new(200, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "OK", "1.1").unwrap();
+    /// connection.send(Event::Response(response)).unwrap();
+    /// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    ///
+    /// connection.start_next_cycle().unwrap();
+    /// assert_eq!(connection.our_state(), Sentinel::Idle);
+    /// assert_eq!(connection.their_state(), Sentinel::Idle);
+    /// ```
+    ///
+    /// A client that asks to close gets a response that says so, and the connection is done:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, Event, Response, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.next_event().unwrap();
+    /// let response = Response::new(200, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "OK", "1.1").unwrap();
+    /// let head = connection.send(Event::Response(response)).unwrap();
+    /// assert!(head.windows(17).any(|w| w == b"Connection: close"));
+    /// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    ///
+    /// assert_eq!(connection.our_state(), Sentinel::MustClose);
+    /// assert!(connection.start_next_cycle().is_err());
+    /// ```
+    pub fn start_next_cycle(&mut self) -> Result<(), ProtocolError> {
+        self.cstate.start_next_cycle()?;
+        self.request_methods.pop_front();
+        // their_http_version is left alone, since it presumably lasts beyond a single cycle
+        // A request we pipelined belongs to the cycle that starts now; its body writer, if it's
+        // still being sent, stays in place
+        if let Some(request) = self.pipelined.pop_front() {
+            let client = role_sentinel(Role::Client);
+            self.cstate.process_event(client, EventType::Request, None)?;
+            if !request.keep_alive {
+                self.cstate.process_keep_alive_disabled();
+            }
+            if request.complete {
+                self.cstate.process_event(client, EventType::EndOfMessage, None)?;
+            }
+        }
+        self.reader = reader_for(role_sentinel(self.their_role), self.their_state(), None, self.allow_obs_fold);
+        Ok(())
+    }
+    /// Returns the method of the request the current cycle is answering, if it has been sent or
+    /// received yet.
+    fn request_method(&self) -> Option<&[u8]> {
+        self.request_methods.front().map(Vec::as_slice)
+    }
+    /// Whether the client has sent `Expect: 100-continue` and is holding back its body until the
+    /// server answers.
+    ///
+    /// On a client, this says we should wait a while for a `100 Continue` before sending the body
+    /// anyway. On a server, it says the client is waiting for us: send an `InformationalResponse`
+    /// with status 100 to get the body, or a final `Response` straight away to refuse it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, InformationalResponse, NextEvent, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection
+    ///     .receive_data(b"PUT / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n")
+    ///     .unwrap();
+    /// connection.next_event().unwrap();
+    /// assert!(connection.client_is_waiting_for_100_continue());
+    /// assert!(connection.they_are_waiting_for_100_continue());
+    ///
+    /// let go_ahead = InformationalResponse::new(100, vec![], "Continue", "1.1").unwrap();
+    /// assert_eq!(connection.send(Event::InformationalResponse(go_ahead)).unwrap(), b"HTTP/1.1 100 Continue\r\n\r\n");
+    /// assert!(!connection.client_is_waiting_for_100_continue());
+    /// ```
+    ///
+    /// A client stops waiting as soon as any response arrives:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, Request, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Client, 16 * 1024);
+    /// let headers = vec![
+    ///     (b"Host".to_vec(), b"a".to_vec()),
+    ///     (b"Content-Length".to_vec(), b"5".to_vec()),
+    ///     (b"Expect".to_vec(), b"100-continue".to_vec()),
+    /// ];
+    /// connection.send(Event::Request(Request::new("PUT", "/", headers, "1.1").unwrap())).unwrap();
+    /// assert!(connection.client_is_waiting_for_100_continue());
+    /// assert!(!connection.they_are_waiting_for_100_continue());
+    ///
+    /// connection.receive_data(b"HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// assert!(!connection.client_is_waiting_for_100_continue());
+    /// ```
+    pub fn client_is_waiting_for_100_continue(&self) -> bool {
+        self.client_is_waiting_for_100_continue
+    }
+    /// Whether the peer is a client waiting for us to answer its `Expect: 100-continue`.
+    pub fn they_are_waiting_for_100_continue(&self) -> bool {
+        self.their_role == Role::Client && self.client_is_waiting_for_100_continue
+    }
+    /// Returns the bytes we received but haven't parsed, and whether the peer has closed its side.
+    ///
+    /// Once both sides reach `SwitchedProtocol`, whatever follows the switch belongs to the new
+    /// protocol; hand these bytes to its implementation along with the socket.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, InformationalResponse, NextEvent, Role, Sentinel};
+    /// use yo::http1::state;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection
+    ///     .receive_data(b"GET /chat HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n\x81\x00")
+    ///     .unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.next_event().unwrap();
+    /// assert_eq!(connection.their_state(), state::Sentinel::MightSwitchProtocol);
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Sentinel(Sentinel::Paused))));
+    ///
+    /// let headers = vec![
+    ///     (b"Upgrade".to_vec(), b"websocket".to_vec()),
+    ///     (b"Connection".to_vec(), b"Upgrade".to_vec()),
+    /// ];
+    /// let switching = InformationalResponse::new(101, headers, "Switching Protocols", "1.1").unwrap();
+    /// connection.send(Event::InformationalResponse(switching)).unwrap();
+    /// assert_eq!(connection.our_state(), state::Sentinel::SwitchedProtocol);
+    /// assert_eq!(connection.their_state(), state::Sentinel::SwitchedProtocol);
+    /// assert_eq!(connection.trailing_data(), (b"\x81\x00".to_vec(), false));
+    /// ```
+    ///
+    /// A server that turns the proposal down with a normal response stays on HTTP/1.1:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, Response, Role};
+    /// use yo::http1::state;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let response = Response::new(403, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "Forbidden", "1.1").unwrap();
+    /// connection.send(Event::Response(response)).unwrap();
+    /// assert_eq!(connection.their_state(), state::Sentinel::Done);
+    /// ```
+    pub fn trailing_data(&self) -> (Vec<u8>, bool) {
+        (self.receive_buffer.to_vec(), self.receive_buffer_closed)
+    }
+    /// Returns the HTTP version the peer announced in its last message head, e.g. `1.1`.
+    pub fn their_http_version(&self) -> Option<&[u8]> {
+        self.their_http_version.as_deref()
+    }
     /// Receives and stores incoming data in the connection's buffer.
-    /// 
+    ///
+    /// Passing an empty slice signals that the peer closed its side of the connection.
+    ///
     /// # Example
-    /// 
+    ///
     /// ```rust
+    /// use yo::http1::connection::{Connection, Role};
+    ///
     /// let mut connection = Connection::new(Role::Client, 16 * 1024);
-    /// connection.receive_data(b"Some data");
-    /// assert!(!connection.receive_buffer.is_empty());
+    /// assert!(connection.receive_data(b"Some data").is_ok());
+    /// assert!(connection.receive_data(b"").is_ok());
+    /// assert!(connection.receive_data(b"more").is_err());
     /// ```
-    pub fn receive_data(&mut self, data: &[u8]) -> Result<(), String> {
+    pub fn receive_data(&mut self, data: &[u8]) -> Result<(), ProtocolError> {
         if data.is_empty() {
             self.receive_buffer_closed = true;
         } else {
-            self.receive_buffer.add_data(data);
+            if self.receive_buffer_closed {
+                return Err(ProtocolError::local(
+                    ErrorKind::UnexpectedData,
+                    "received close, then received more data?",
+                ));
+            }
+            self.receive_buffer.append(data);
         }
         Ok(())
     }
     /// Retrieves the next event for the connection based on its current state and data.
-    /// 
+    ///
+    /// Returns `Sentinel::NeedData` when more bytes have to be passed to `receive_data` before an
+    /// event can be produced, and `Sentinel::Paused` when the peer has finished its message and
+    /// anything still buffered belongs to the next one.
+    ///
     /// # Example
-    /// 
+    ///
     /// ```rust
-    /// let mut connection = Connection::new(Role::Client, 16 * 1024);
-    /// connection.receive_data(b"Some data");
-    /// let event = connection.next_event();
-    /// assert!(event.is_ok());
+    /// use yo::http1::{Connection, Event, NextEvent, Role, Sentinel};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nhi").unwrap();
+    ///
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Request(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(data))) if &data.data[..] == b"hi"));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Sentinel(Sentinel::NeedData))));
+    /// ```
+    ///
+    /// Pipelined requests wait in the buffer until we have answered the current one:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Response, Role, Sentinel};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET /1 HTTP/1.1\r\nHost: a\r\n\r\nGET /2 HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Request(r))) if r.target == b"/1"));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Sentinel(Sentinel::Paused))));
+    ///
+    /// let response = Response::new(200, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "OK", "1.1").unwrap();
+    /// connection.send(Event::Response(response)).unwrap();
+    /// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    /// connection.start_next_cycle().unwrap();
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Request(r))) if r.target == b"/2"));
+    /// ```
+    ///
+    /// A head that keeps growing past `max_incomplete_event_size` is refused rather than
+    /// buffered forever:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, ErrorKind, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 64);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.receive_data(&[b'X'; 64]).unwrap();
+    /// let err = connection.next_event().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::OversizedHead);
+    /// assert_eq!(err.error_status_hint(), 431);
+    /// ```
+    ///
+    /// The same goes for the size line of each chunk in a chunked body:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, ErrorKind, Event, NextEvent, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 64);
+    /// connection.receive_data(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    /// connection.receive_data(b"2\r\nhi\r\n").unwrap();
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(d))) if &d.data[..] == b"hi"));
+    /// connection.receive_data(&[b'0'; 65]).unwrap();
+    /// let err = connection.next_event().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::OversizedChunkLine);
+    /// assert_eq!(err.error_status_hint(), 400);
     /// ```
-    pub fn next_event(&mut self) -> Result<Event, String> {
-        if self.cstate == ConnectionState::Error {
-            return Err("Cannot process events in the ERROR state.".to_string());
+    ///
+    /// Anything the peer got wrong comes back as a remote error, tagged with where in the
+    /// received stream the offending event started:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, ErrorKind, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").unwrap();
+    /// let err = connection.next_event().unwrap_err();
+    /// assert!(err.is_remote());
+    /// assert_eq!(err.kind(), ErrorKind::BadContentLength);
+    /// assert_eq!(err.error_status_hint(), 400);
+    /// assert_eq!(err.offset(), Some(0));
+    /// ```
+    pub fn next_event(&mut self) -> Result<NextEvent, ProtocolError> {
+        if self.their_state() == state::Sentinel::Error {
+            return Err(ProtocolError::remote(
+                ErrorKind::ErrorState,
+                "Can't receive data when peer state is ERROR",
+            ));
         }
 
-        if self.receive_buffer.is_empty() && self.receive_buffer_closed {
-            return Ok(Event::ConnectionClosed);
+        let offset = self.receive_buffer.consumed();
+        let result = self.extract_next_receive_event().and_then(|next| match next {
+            NextEvent::Event(event) => {
+                self.process_event(self.their_role, &event)?;
+                Ok(NextEvent::Event(event))
+            }
+            // Whatever we are waiting for can't be allowed to grow without bound
+            NextEvent::Sentinel(Sentinel::NeedData)
+                if self.receive_buffer.len() > self.max_incomplete_event_size =>
+            {
+                Err(self.reader.as_ref().map_or_else(
+                    || ProtocolError::remote(ErrorKind::OversizedHead, "Receive buffer too long"),
+                    |reader| reader.oversized_error(),
+                ))
+            }
+            NextEvent::Sentinel(Sentinel::NeedData) if self.receive_buffer_closed => Err(
+                ProtocolError::remote(ErrorKind::IncompleteMessage, "peer unexpectedly closed connection"),
+            ),
+            sentinel => Ok(sentinel),
+        });
+        result.map_err(|e| {
+            self.cstate.process_error(role_sentinel(self.their_role));
+            e.into_remote().with_offset(offset)
+        })
+    }
+    /// Pulls the next event out of the receive buffer using the reader for the peer's state.
+    ///
+    /// States with no reader, like those around a protocol switch, pause the connection; so does
+    /// a finished peer with more data waiting, until the next cycle starts.
+    fn extract_next_receive_event(&mut self) -> Result<NextEvent, ProtocolError> {
+        if self.their_state() == state::Sentinel::Done && !self.receive_buffer.is_empty() {
+            return Ok(NextEvent::Sentinel(Sentinel::Paused));
         }
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(NextEvent::Sentinel(Sentinel::Paused));
+        };
 
-        // Implement event extraction logic here
-        Err("next_event not fully implemented".to_string())
+        let mut event = reader.read(&mut self.receive_buffer)?;
+        if event.is_none() && self.receive_buffer.is_empty() && self.receive_buffer_closed {
+            event = Some(reader.read_eof()?);
+        }
+        Ok(match event {
+            Some(event) => NextEvent::Event(event),
+            None => NextEvent::Sentinel(Sentinel::NeedData),
+        })
+    }
+    /// Works out how the body following a request or final response head is framed, whichever
+    /// side is sending it; other events have no body of their own.
+    fn body_framing(&self, event: &Event) -> Result<Option<Framing>, ProtocolError> {
+        match event {
+            Event::Request(request) => body_framing(
+                Role::Client,
+                &request.method,
+                0,
+                &request.headers,
+                &request.http_version,
+            )
+            .map(Some),
+            Event::Response(response) => self.response_framing(response).map(Some),
+            _ => Ok(None),
+        }
+    }
+    /// Works out how the body of a final response is framed, given the request it answers.
+    fn response_framing(&self, response: &Response) -> Result<Framing, ProtocolError> {
+        body_framing(
+            Role::Server,
+            self.request_method().unwrap_or_default(),
+            response.status_code,
+            &response.headers,
+            &response.http_version,
+        )
+    }
+    /// Runs an event sent by `role` through the state machine, then swaps in the reader and
+    /// writer for whichever states that moved each side into.
+    ///
+    /// The reader always follows the peer's state. Our body writer is only replaced by events we
+    /// send ourselves, so one held by a pipelined request survives what the peer does meanwhile.
+    fn process_event(&mut self, role: Role, event: &Event) -> Result<(), ProtocolError> {
+        let framing = self.body_framing(event)?;
+        let (our_old_state, their_old_state) = (self.our_state(), self.their_state());
+        self.update_state(role, event)?;
+
+        let their_state = self.their_state();
+        if their_state != their_old_state {
+            let framing = framing.filter(|_| role == self.their_role);
+            self.reader = reader_for(role_sentinel(self.their_role), their_state, framing, self.allow_obs_fold);
+        }
+        let our_state = self.our_state();
+        if role == self.our_role && our_state != our_old_state {
+            self.writer = framing.filter(|_| our_state == state::Sentinel::SendBody).map(body_writer);
+        }
+        Ok(())
+    }
+    /// Records what an event sent by `role` tells us about the connection: the request method,
+    /// the peer's HTTP version, and whether either side wants the connection closed after this
+    /// cycle.
+    fn update_state(&mut self, role: Role, event: &Event) -> Result<(), ProtocolError> {
+        if let (Role::Client, Event::Request(request)) = (role, event) {
+            if request.method == b"CONNECT" {
+                self.cstate.process_client_switch_proposal(state::Sentinel::SwitchConnect);
+            }
+            if !request.headers.get_comma_header(b"upgrade").is_empty() {
+                self.cstate.process_client_switch_proposal(state::Sentinel::SwitchUpgrade);
+            }
+        }
+        let server_switch_event = match role {
+            Role::Server => self.server_switch_event(event),
+            Role::Client => None,
+        };
+        self.cstate
+            .process_event(role_sentinel(role), event.event_type(), server_switch_event)?;
+        match event {
+            Event::Request(request) => {
+                self.request_methods.push_back(request.method.clone());
+                // HTTP/1.0 clients can't use Expect, so servers have to ignore it from them
+                self.client_is_waiting_for_100_continue =
+                    request.http_version[..] >= b"1.1"[..] && request.headers.has_expect_100_continue();
+            }
+            // Any response ends the wait, and so does the client sending its body anyway
+            Event::InformationalResponse(_) | Event::Response(_) => {
+                self.client_is_waiting_for_100_continue = false;
+            }
+            Event::Data(_) | Event::EndOfMessage(_) if role == Role::Client => {
+                self.client_is_waiting_for_100_continue = false;
+            }
+            _ => {}
+        }
+        let (headers, http_version) = match event {
+            Event::Request(request) => (&request.headers, &request.http_version),
+            Event::InformationalResponse(response) => (&response.headers, &response.http_version),
+            Event::Response(response) => (&response.headers, &response.http_version),
+            _ => return Ok(()),
+        };
+        if role == self.their_role {
+            self.their_http_version = Some(http_version.clone());
+        }
+        if !matches!(event, Event::InformationalResponse(_)) && !keep_alive(headers, http_version) {
+            self.cstate.process_keep_alive_disabled();
+        }
+        Ok(())
+    }
+    /// Works out whether a server event accepts one of the client's switch proposals: a `101`
+    /// accepts an `Upgrade:`, and a 2xx response accepts a `CONNECT`.
+    fn server_switch_event(&self, event: &Event) -> Option<state::Sentinel> {
+        match event {
+            Event::InformationalResponse(response) if response.status_code == 101 => {
+                Some(state::Sentinel::SwitchUpgrade)
+            }
+            Event::Response(response)
+                if self
+                    .cstate
+                    .pending_switch_proposals
+                    .contains(&state::Sentinel::SwitchConnect)
+                    && (200..300).contains(&response.status_code) =>
+            {
+                Some(state::Sentinel::SwitchConnect)
+            }
+            _ => None,
+        }
     }
     /// Sends the given event by serializing it to bytes based on the connection's state.
-    /// 
+    ///
+    /// Sending a `Request` or `Response` picks the body framing for the rest of the message from
+    /// its headers (and, for responses, from the peer's HTTP version), so the `Data` and
+    /// `EndOfMessage` events that follow come back as correctly framed bytes.
+    ///
     /// # Example
-    /// 
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Data, EndOfMessage, Event, Request, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Client, 16 * 1024);
+    /// let headers = vec![
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"Transfer-Encoding".to_vec(), b"chunked".to_vec()),
+    /// ];
+    /// let request = Request::new("POST", "/upload", headers, "1.1").unwrap();
+    /// assert!(connection.send(Event::Request(request)).unwrap().starts_with(b"POST /upload HTTP/1.1\r\n"));
+    /// let data = Data::new(b"hello".to_vec(), false, false);
+    /// assert_eq!(connection.send(Event::Data(data)).unwrap(), b"5\r\nhello\r\n");
+    /// assert_eq!(connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap(), b"0\r\n\r\n");
+    /// ```
+    ///
+    /// An HTTP/1.0 client can't decode chunked bodies, so an unframed response to it is
+    /// delimited by closing the connection:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Data, EndOfMessage, Event, Response, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let response = Response::new(200, vec![], "OK", "1.1").unwrap();
+    /// let head = connection.send(Event::Response(response)).unwrap();
+    /// assert!(head.windows(17).any(|w| w == b"Connection: close"));
+    /// let data = Data::new(b"hello".to_vec(), false, false);
+    /// assert_eq!(connection.send(Event::Data(data)).unwrap(), b"hello");
+    /// assert_eq!(connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap(), b"");
+    /// ```
+    ///
+    /// Other transfer codings are left to the application; to HTTP/1.1 peers, `chunked` is
+    /// applied on top of them:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, Response, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let response = Response::new(200, vec![(b"Transfer-Encoding".to_vec(), b"gzip".to_vec())], "OK", "1.1").unwrap();
+    /// let head = connection.send(Event::Response(response)).unwrap();
+    /// assert!(head.windows(32).any(|w| w == b"Transfer-Encoding: gzip, chunked"));
+    /// ```
+    ///
+    /// HTTP/1.0 peers don't understand transfer codings at all, so a coded body can't be sent
+    /// to them; only `chunked` is dropped, since the connection can frame the body by closing:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Event, Response, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// connection.receive_data(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    /// connection.next_event().unwrap();
+    ///
+    /// let response = Response::new(200, vec![(b"Transfer-Encoding".to_vec(), b"gzip".to_vec())], "OK", "1.1").unwrap();
+    /// let error = connection.send(Event::Response(response)).unwrap_err();
+    /// assert!(error.message().contains("gzip"));
+    /// ```
+    ///
+    /// A client may send further requests once the current one is complete, without waiting for
+    /// the response. Each response is then framed according to the request it answers:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Request, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Client, 16 * 1024);
+    /// for method in ["HEAD", "GET"] {
+    ///     let request = Request::new(method, "/", vec![(b"Host".to_vec(), b"a".to_vec())], "1.1").unwrap();
+    ///     connection.send(Event::Request(request)).unwrap();
+    ///     connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    /// }
+    ///
+    /// connection.receive_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n").unwrap();
+    /// connection.receive_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+    /// // The response to HEAD has no body, whatever its Content-Length says
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Response(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Sentinel(yo::http1::Sentinel::Paused))));
+    ///
+    /// connection.start_next_cycle().unwrap();
+    /// assert_eq!(connection.our_state(), Sentinel::Done);
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Response(_)))));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(d))) if &d.data[..] == b"hello"));
+    /// ```
+    ///
+    /// A request that proposes switching protocols, or that waits for `100 Continue`, needs the
+    /// server's answer before anything else can follow it, so it can't be pipelined:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, ErrorKind, Event, Request, Role};
+    ///
+    /// let proposals = [
+    ///     ("GET", vec![(b"Upgrade".to_vec(), b"websocket".to_vec()), (b"Connection".to_vec(), b"upgrade".to_vec())]),
+    ///     ("CONNECT", vec![]),
+    ///     ("POST", vec![(b"Expect".to_vec(), b"100-continue".to_vec()), (b"Content-Length".to_vec(), b"0".to_vec())]),
+    /// ];
+    /// for (method, mut headers) in proposals {
+    ///     let mut connection = Connection::new(Role::Client, 16 * 1024);
+    ///     let request = Request::new("GET", "/", vec![(b"Host".to_vec(), b"a".to_vec())], "1.1").unwrap();
+    ///     connection.send(Event::Request(request)).unwrap();
+    ///     connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    ///
+    ///     headers.push((b"Host".to_vec(), b"a".to_vec()));
+    ///     let request = Request::new(method, "a:443", headers, "1.1").unwrap();
+    ///     let err = connection.send(Event::Request(request)).unwrap_err();
+    ///     assert_eq!(err.kind(), ErrorKind::IllegalEvent);
+    ///     assert!(err.message().contains("protocol switch or expects 100-continue"));
+    /// }
+    /// ```
+    ///
+    /// Mistakes on our side come back as local errors, tagged with how many bytes had already
+    /// been sent:
+    ///
     /// ```rust
+    /// use yo::http1::{Connection, Data, ErrorKind, Event, Request, Role};
+    ///
     /// let mut connection = Connection::new(Role::Client, 16 * 1024);
-    /// let event = Event::Request(Request { method: b"GET".to_vec(), headers: HashMap::new(), http_version: b"HTTP/1.1".to_vec() });
-    /// let result = connection.send(event);
-    /// assert!(result.is_ok());
+    /// let headers = vec![
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"Content-Length".to_vec(), b"2".to_vec()),
+    /// ];
+    /// let head = connection.send(Event::Request(Request::new("POST", "/", headers, "1.1").unwrap())).unwrap();
+    /// let err = connection.send(Event::Data(Data::new(b"too long".to_vec(), false, false))).unwrap_err();
+    /// assert!(err.is_local());
+    /// assert_eq!(err.kind(), ErrorKind::BodyLengthMismatch);
+    /// assert_eq!(err.offset(), Some(head.len()));
+    /// ```
+    ///
+    /// Events that don't fit the state machine are rejected, and put our side into the error
+    /// state:
+    ///
+    /// ```rust
+    /// use yo::http1::{Connection, Data, ErrorKind, Event, Role};
+    /// use yo::http1::state::Sentinel;
+    ///
+    /// let mut connection = Connection::new(Role::Server, 16 * 1024);
+    /// let err = connection.send(Event::Data(Data::new(b"early".to_vec(), false, false))).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::IllegalEvent);
+    /// assert_eq!(connection.our_state(), Sentinel::Error);
     /// ```
-    pub fn send(&mut self, event: Event) -> Result<Vec<u8>, String> {
-        if self.cstate == ConnectionState::Error {
-            return Err("Cannot send data in the ERROR state.".to_string());
+    pub fn send(&mut self, event: Event) -> Result<Vec<u8>, ProtocolError> {
+        if self.our_state() == state::Sentinel::Error {
+            return Err(ProtocolError::local(
+                ErrorKind::ErrorState,
+                "Cannot send data in the ERROR state.",
+            ));
         }
 
+        match self.send_event(event) {
+            Ok(out) => {
+                self.bytes_sent += out.len();
+                Ok(out)
+            }
+            Err(e) => {
+                self.cstate.process_error(role_sentinel(self.our_role));
+                Err(e.with_offset(self.bytes_sent))
+            }
+        }
+    }
+    /// Serializes one event and advances our side of the connection.
+    fn send_event(&mut self, mut event: Event) -> Result<Vec<u8>, ProtocolError> {
+        if self.our_role == Role::Client
+            && self.our_state() == state::Sentinel::Done
+            && (event.event_type() == EventType::Request
+                || self.pipelined.back().is_some_and(|request| !request.complete))
+        {
+            return self.send_pipelined(event);
+        }
+        if let Event::Response(response) = &mut event {
+            if self.send_date && !response.headers.contains(b"date") {
+                response.headers.append(b"Date", &format_http_date((self.clock)()))?;
+            }
+            self.frame_response(response)?;
+        }
+        // The writer is picked by the state we're in before the event moves us on
+        let head_writer = WRITERS.get(&(role_sentinel(self.our_role), self.our_state())).copied();
+        let body_writer = match event {
+            Event::Data(_) | Event::EndOfMessage(_) => self.writer.take(),
+            _ => None,
+        };
+        self.process_event(self.our_role, &event)?;
+        let mut out = Vec::new();
+        match event {
+            Event::Request(_) | Event::InformationalResponse(_) | Event::Response(_) => {
+                let write = head_writer.ok_or_else(|| {
+                    ProtocolError::local(ErrorKind::IllegalEvent, "Can't send a head in this state")
+                })?;
+                write(&event, &mut out)?;
+            }
+            Event::Data(data) => {
+                let mut writer = body_writer.ok_or_else(|| {
+                    ProtocolError::local(ErrorKind::IllegalEvent, "Can't send data when not in SEND_BODY state")
+                })?;
+                writer.send_data(&data.data, &mut out)?;
+                self.writer = Some(writer);
+            }
+            Event::EndOfMessage(eom) => {
+                let mut writer = body_writer.ok_or_else(|| {
+                    ProtocolError::local(ErrorKind::IllegalEvent, "Can't end a message when not in SEND_BODY state")
+                })?;
+                writer.send_eom(eom.headers, &mut out)?;
+            }
+            // Closing is up to the caller; there is nothing to put on the wire
+            Event::ConnectionClosed(_) => {}
+        }
+        Ok(out)
+    }
+    /// Serializes an event of a request sent ahead of the current cycle, while we wait for the
+    /// response to an earlier one.
+    ///
+    /// Pipelining is only allowed while every earlier request keeps the connection alive, and
+    /// one request has to be finished before the next one starts. Requests that propose a switch
+    /// or expect `100 Continue` have to wait for the server, so they are never pipelined.
+    fn send_pipelined(&mut self, event: Event) -> Result<Vec<u8>, ProtocolError> {
+        let framing = self.body_framing(&event)?;
+        let mut out = Vec::new();
         match event {
-            Event::Request(req) => Ok(serialize_request(req)),
-            Event::Response(res) => Ok(serialize_response(res)),
-            _ => Err("Unsupported event type".to_string()),
+            Event::Request(req) => {
+                let can_pipeline = self.cstate.keep_alive
+                    && self.cstate.pending_switch_proposals.is_empty()
+                    && self.pipelined.iter().all(|request| request.keep_alive && request.complete);
+                if !can_pipeline {
+                    return Err(ProtocolError::local(
+                        ErrorKind::IllegalEvent,
+                        "can't pipeline a request on a connection that won't be reused",
+                    ));
+                }
+                // Replaying the request at the start of its cycle only moves the state machine;
+                // a proposal or a 100-continue wait it carries would go unrecorded
+                let proposes_switch = req.method == b"CONNECT" || !req.headers.get_comma_header(b"upgrade").is_empty();
+                let expects_continue = req.http_version[..] >= b"1.1"[..] && req.headers.has_expect_100_continue();
+                if proposes_switch || expects_continue {
+                    return Err(ProtocolError::local(
+                        ErrorKind::IllegalEvent,
+                        "can't pipeline a request that proposes a protocol switch or expects 100-continue",
+                    ));
+                }
+                write_request(&req, &mut out)?;
+                self.writer = framing.map(body_writer);
+                self.request_methods.push_back(req.method);
+                self.pipelined.push_back(PipelinedRequest {
+                    keep_alive: keep_alive(&req.headers, &req.http_version),
+                    complete: false,
+                });
+            }
+            Event::Data(data) => {
+                if let Some(writer) = self.writer.as_mut() {
+                    writer.send_data(&data.data, &mut out)?;
+                }
+            }
+            Event::EndOfMessage(eom) => {
+                if let Some(mut writer) = self.writer.take() {
+                    writer.send_eom(eom.headers, &mut out)?;
+                }
+                if let Some(request) = self.pipelined.back_mut() {
+                    request.complete = true;
+                }
+            }
+            _ => {
+                return Err(ProtocolError::local(
+                    ErrorKind::IllegalEvent,
+                    format!("can't send {:?} while a pipelined request is in progress", event.event_type()),
+                ));
+            }
+        }
+        Ok(out)
+    }
+    /// Fixes up the framing headers of a response we are about to send, so that `body_framing`
+    /// decides on what will actually go on the wire.
+    ///
+    /// The framing starts out as `body_framing` decides from the response as given. A response
+    /// whose body would run until close is sent chunked to HTTP/1.1 peers instead, with `chunked`
+    /// added after any transfer codings the response already lists. HTTP/1.0 peers (or peers
+    /// whose version we never learned) can't decode chunked bodies, so they get a body delimited
+    /// by closing the connection even if chunked was asked for; any other transfer coding would
+    /// reach them undeclared, so that is an error instead. Either that, or keep-alive having
+    /// been turned off by an earlier message, makes the response carry `Connection: close`.
+    fn frame_response(&self, response: &mut Response) -> Result<(), ProtocolError> {
+        let set = |headers: &Headers, name: &[u8], values: Vec<Vec<u8>>| headers.set_comma_header(name, values);
+        let mut headers = response.headers.clone();
+        let mut need_close = false;
+        match self.response_framing(response)? {
+            Framing::Chunked | Framing::ReadUntilClose => {
+                headers = set(&headers, b"content-length", vec![])?;
+                let peer_speaks_chunked = matches!(self.their_http_version.as_deref(), Some(v) if v >= &b"1.1"[..]);
+                let mut codings = headers.transfer_codings();
+                if !peer_speaks_chunked {
+                    codings.retain(|coding| coding != b"chunked");
+                    if !codings.is_empty() {
+                        return Err(ProtocolError::local(
+                            ErrorKind::UnsupportedTransferEncoding,
+                            format!(
+                                "can't send a body with transfer codings {:?} to a peer that doesn't support them",
+                                String::from_utf8_lossy(&codings.join(&b", "[..]))
+                            ),
+                        ));
+                    }
+                    headers = set(&headers, b"transfer-encoding", vec![])?;
+                    need_close = true;
+                } else if !codings.iter().any(|coding| coding == b"chunked") {
+                    // Chunked goes on top of whatever codings the body already has
+                    codings.push(b"chunked".to_vec());
+                    headers = set(&headers, b"transfer-encoding", vec![codings.join(&b", "[..])])?;
+                } else if codings.last().is_some_and(|coding| coding != b"chunked") {
+                    // A body chunked before being coded again can only end with the connection
+                    need_close = true;
+                }
+            }
+            _ => {}
         }
+        if !self.cstate.keep_alive || need_close {
+            let mut connection = headers.get_comma_header(b"connection");
+            connection.retain(|token| token != b"keep-alive" && token != b"close");
+            connection.push(b"close".to_vec());
+            headers = set(&headers, b"connection", connection)?;
+        }
+        response.headers = headers;
+        Ok(())
     }
 }
-/// Serializes an HTTP request into a byte vector.
-///
-/// # Example
-/// 
-/// ```rust
-/// let request = Request {
-///     method: b"GET".to_vec(),
-///     headers: HashMap::new(),
-///     http_version: b"1.1".to_vec(),
-/// };
-/// let serialized = serialize_request(request);
-/// assert!(serialized.starts_with(b"GET"));
-/// ```
-fn serialize_request(request: Request) -> Vec<u8> {
-    let mut output = Vec::new();
-    output.extend(request.method);
-    output.extend(b" / HTTP/");
-    output.extend(request.http_version);
-    output.extend(b"\r\n");
-    for (key, value) in request.headers {
-        output.extend(key);
-        output.extend(b": ");
-        output.extend(value);
-        output.extend(b"\r\n");
+/// Whether a message lets the connection be reused afterwards: HTTP/1.0 messages and messages
+/// carrying `Connection: close` turn keep-alive off.
+fn keep_alive(headers: &Headers, http_version: &[u8]) -> bool {
+    if headers.get_comma_header(b"connection").iter().any(|token| token == b"close") {
+        return false;
     }
-    output.extend(b"\r\n");
-    output
+    http_version >= &b"1.1"[..]
 }
-/// Serializes an HTTP response into a byte vector.
-///
-/// # Example
-/// 
-/// ```rust
-/// let response = Response {
-///     status_code: 200,
-///     reason: b"OK".to_vec(),
-///     headers: HashMap::new(),
-///     http_version: b"1.1".to_vec(),
-/// };
-/// let serialized = serialize_response(response);
-/// assert!(serialized.starts_with(b"HTTP/"));
-/// ```
-fn serialize_response(response: Response) -> Vec<u8> {
-    let mut output = Vec::new();
-    output.extend(b"HTTP/");
-    output.extend(response.http_version);
-    output.extend(b" ");
-    output.extend(response.status_code.to_string().as_bytes());
-    output.extend(b" ");
-    output.extend(response.reason);
-    output.extend(b"\r\n");
-    for (key, value) in response.headers {
-        output.extend(key);
-        output.extend(b": ");
-        output.extend(value);
-        output.extend(b"\r\n");
+/// Maps a connection role onto the role sentinel used by the state machine.
+fn role_sentinel(role: Role) -> state::Sentinel {
+    match role {
+        Role::Client => state::Sentinel::Client,
+        Role::Server => state::Sentinel::Server,
     }
-    output.extend(b"\r\n");
-    output
 }
-
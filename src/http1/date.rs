@@ -0,0 +1,300 @@
+//! HTTP-date parsing and formatting, RFC 9110 section 5.6.7.
+//!
+//! Dates are sent in the IMF-fixdate format only, but all three formats are accepted when
+//! parsing: IMF-fixdate, the obsolete RFC 850 format and C's `asctime()` format. Timestamps are
+//! plain `SystemTime`s, converted with the days-from-civil algorithm, so no time crate is needed.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use yo::http1::date::{format_http_date, parse_http_date, parse_http_date_at};
+//!
+//! let time = UNIX_EPOCH + Duration::from_secs(784111777);
+//! assert_eq!(parse_http_date(b"Sun, 06 Nov 1994 08:49:37 GMT").unwrap(), time);
+//! assert_eq!(parse_http_date_at(b"Sunday, 06-Nov-94 08:49:37 GMT", time).unwrap(), time);
+//! assert_eq!(parse_http_date(b"Sun Nov  6 08:49:37 1994").unwrap(), time);
+//! assert_eq!(format_http_date(time), b"Sun, 06 Nov 1994 08:49:37 GMT");
+//!
+//! assert!(parse_http_date(b"Sun, 31 Nov 1994 08:49:37 GMT").is_err());
+//! assert!(parse_http_date(b"Sun, 06 Nov 1994 08:49:37 UTC").is_err());
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::util::{ErrorKind, ProtocolError};
+
+/// Where a connection gets the current time for the `Date` headers it adds.
+pub type Clock = Box<dyn Fn() -> SystemTime + Send + Sync>;
+
+const DAY_NAMES: [&[u8]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+const LONG_DAY_NAMES: [&[u8]; 7] = [b"Sunday", b"Monday", b"Tuesday", b"Wednesday", b"Thursday", b"Friday", b"Saturday"];
+const MONTH_NAMES: [&[u8]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+/// A calendar date and time of day in GMT, as read off the wire.
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+/// Parses an HTTP-date in any of its three formats.
+///
+/// A two-digit RFC 850 year is placed relative to the current year, as RFC 9110 section 5.6.7
+/// asks: in the coming 50 years if that's possible, and in the past otherwise. Use
+/// `parse_http_date_at` to pick what "current" means. A leap second is accepted and rolls over
+/// into the next minute.
+///
+/// # Arguments
+/// * `value` - The date, e.g. the value of a `Last-Modified` header.
+///
+/// # Returns
+/// The point in time, or a `BadHeader` error if the date doesn't parse or doesn't exist.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::UNIX_EPOCH;
+/// use yo::http1::date::parse_http_date;
+///
+/// assert_eq!(parse_http_date(b"Thu, 01 Jan 1970 00:00:00 GMT").unwrap(), UNIX_EPOCH);
+/// assert!(parse_http_date(b"Thursday, 01-Jan-70 00:00:00 GMT").is_ok());
+/// assert!(parse_http_date(b"Thu, 1 Jan 1970 00:00:00 GMT").is_err());
+/// ```
+pub fn parse_http_date(value: &[u8]) -> Result<SystemTime, ProtocolError> {
+    parse_http_date_at(value, SystemTime::now())
+}
+
+/// Parses an HTTP-date like `parse_http_date`, placing a two-digit RFC 850 year relative to
+/// `now` instead of the system clock.
+///
+/// # Arguments
+/// * `value` - The date.
+/// * `now` - The time the date was received.
+///
+/// # Returns
+/// The point in time, or a `BadHeader` error if the date doesn't parse or doesn't exist.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use yo::http1::date::{parse_http_date, parse_http_date_at};
+///
+/// let now = parse_http_date(b"Sat, 17 Oct 2026 00:00:00 GMT").unwrap();
+/// let in_2069 = parse_http_date(b"Tue, 01 Jan 2069 00:00:00 GMT").unwrap();
+/// let in_1977 = parse_http_date(b"Sat, 01 Jan 1977 00:00:00 GMT").unwrap();
+/// assert_eq!(parse_http_date_at(b"Tuesday, 01-Jan-69 00:00:00 GMT", now).unwrap(), in_2069);
+/// // 2077 would be more than 50 years away, so it is the most recent '77 instead
+/// assert_eq!(parse_http_date_at(b"Saturday, 01-Jan-77 00:00:00 GMT", now).unwrap(), in_1977);
+///
+/// // Late in a century, small years belong to the next one
+/// let now = UNIX_EPOCH + Duration::from_secs(4_070_908_800); // 2099-01-01
+/// let in_2105 = parse_http_date(b"Thu, 01 Jan 2105 00:00:00 GMT").unwrap();
+/// assert_eq!(parse_http_date_at(b"Thursday, 01-Jan-05 00:00:00 GMT", now).unwrap(), in_2105);
+/// ```
+pub fn parse_http_date_at(value: &[u8], now: SystemTime) -> Result<SystemTime, ProtocolError> {
+    parse_imf_fixdate(value)
+        .or_else(|| parse_rfc850_date(value, current_year(now)))
+        .or_else(|| parse_asctime_date(value))
+        .and_then(to_system_time)
+        .ok_or_else(|| {
+            ProtocolError::local(
+                ErrorKind::BadHeader,
+                format!("invalid HTTP-date {:?}", String::from_utf8_lossy(value)),
+            )
+        })
+}
+
+/// Formats a point in time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`. Fractions
+/// of a second are dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use yo::http1::date::format_http_date;
+///
+/// assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_millis(1500)), b"Thu, 01 Jan 1970 00:00:01 GMT");
+/// assert_eq!(format_http_date(UNIX_EPOCH - Duration::from_secs(1)), b"Wed, 31 Dec 1969 23:59:59 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> Vec<u8> {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        // Round down, so a time just before the epoch isn't formatted as the epoch itself
+        Err(before) => {
+            let before = before.duration();
+            -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    let days = seconds.div_euclid(86400);
+    let time_of_day = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        String::from_utf8_lossy(DAY_NAMES[(days + 4).rem_euclid(7) as usize]),
+        day,
+        String::from_utf8_lossy(MONTH_NAMES[month as usize - 1]),
+        year,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+    )
+    .into_bytes()
+}
+
+/// Parses `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_imf_fixdate(value: &[u8]) -> Option<DateTime> {
+    if value.len() != 29 || !DAY_NAMES.contains(&&value[..3]) || &value[3..5] != b", " {
+        return None;
+    }
+    let (day, month, year) = (&value[5..7], &value[8..11], &value[12..16]);
+    if value[7] != b' ' || value[11] != b' ' || value[16] != b' ' || &value[25..] != b" GMT" {
+        return None;
+    }
+    let (hour, minute, second) = parse_time_of_day(&value[17..25])?;
+    Some(DateTime {
+        year: parse_digits(year)?.into(),
+        month: parse_month(month)?,
+        day: parse_digits(day)?,
+        hour,
+        minute,
+        second,
+    })
+}
+
+/// Parses `Sunday, 06-Nov-94 08:49:37 GMT`, taking the two-digit year to be the one that
+/// isn't more than 50 years after `current_year`, and is closest to it.
+fn parse_rfc850_date(value: &[u8], current_year: i64) -> Option<DateTime> {
+    let comma = value.iter().position(|&b| b == b',')?;
+    if !LONG_DAY_NAMES.contains(&&value[..comma]) {
+        return None;
+    }
+    let rest = value[comma..].strip_prefix(b", ")?;
+    if rest.len() != 22 || rest[2] != b'-' || rest[6] != b'-' || rest[9] != b' ' || &rest[18..] != b" GMT" {
+        return None;
+    }
+    let short_year = i64::from(parse_digits(&rest[7..9])?);
+    let (hour, minute, second) = parse_time_of_day(&rest[10..18])?;
+    let mut year = current_year - current_year.rem_euclid(100) + short_year;
+    if year > current_year + 50 {
+        year -= 100;
+    } else if year + 100 <= current_year + 50 {
+        year += 100;
+    }
+    Some(DateTime {
+        year,
+        month: parse_month(&rest[3..6])?,
+        day: parse_digits(&rest[..2])?,
+        hour,
+        minute,
+        second,
+    })
+}
+
+/// Parses `Sun Nov  6 08:49:37 1994`, where a one-digit day is padded with a space.
+fn parse_asctime_date(value: &[u8]) -> Option<DateTime> {
+    if value.len() != 24 || !DAY_NAMES.contains(&&value[..3]) {
+        return None;
+    }
+    if value[3] != b' ' || value[7] != b' ' || value[10] != b' ' || value[19] != b' ' {
+        return None;
+    }
+    let day = value[8..10].strip_prefix(b" ").unwrap_or(&value[8..10]);
+    let (hour, minute, second) = parse_time_of_day(&value[11..19])?;
+    Some(DateTime {
+        year: parse_digits(&value[20..])?.into(),
+        month: parse_month(&value[4..7])?,
+        day: parse_digits(day)?,
+        hour,
+        minute,
+        second,
+    })
+}
+
+/// Parses `HH:MM:SS`.
+fn parse_time_of_day(value: &[u8]) -> Option<(u32, u32, u32)> {
+    match value {
+        [h1, h2, b':', m1, m2, b':', s1, s2] => Some((
+            parse_digits(&[*h1, *h2])?,
+            parse_digits(&[*m1, *m2])?,
+            parse_digits(&[*s1, *s2])?,
+        )),
+        _ => None,
+    }
+}
+
+/// Parses a non-empty run of decimal digits.
+fn parse_digits(value: &[u8]) -> Option<u32> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    value.iter().try_fold(0u32, |n, digit| n.checked_mul(10)?.checked_add(u32::from(digit - b'0')))
+}
+
+/// Turns a month name into its number, 1 for January. Names are case-sensitive.
+fn parse_month(value: &[u8]) -> Option<u32> {
+    MONTH_NAMES.iter().position(|&name| name == value).map(|i| i as u32 + 1)
+}
+
+/// Checks that a date and time exist, and converts them into a point in time.
+fn to_system_time(date: DateTime) -> Option<SystemTime> {
+    let days_in_month = match date.month {
+        2 if date.year % 4 == 0 && (date.year % 100 != 0 || date.year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&date.month) || !(1..=days_in_month).contains(&date.day) {
+        return None;
+    }
+    if date.hour > 23 || date.minute > 59 || date.second > 60 {
+        return None;
+    }
+    let seconds = days_from_civil(date.year, date.month, date.day) * 86400
+        + i64::from(date.hour * 3600 + date.minute * 60 + date.second);
+    // SystemTime's range depends on the platform, and a peer can send any year from 0 to 9999
+    match u64::try_from(seconds) {
+        Ok(after) => UNIX_EPOCH.checked_add(Duration::from_secs(after)),
+        Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs())),
+    }
+}
+
+/// Returns the year `now` falls in.
+fn current_year(now: SystemTime) -> i64 {
+    let seconds = match now.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    civil_from_days(seconds.div_euclid(86400)).0
+}
+
+/// Counts the days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = i64::from((month + 9) % 12);
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Turns a count of days from 1970-01-01 back into a year, month and day.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    (year, month, day)
+}
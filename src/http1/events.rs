@@ -1,19 +1,27 @@
-use std::fmt::{Debug, Formatter, Result as FmtResult}; 
-use std::result::Result as StdResult; 
+use std::result::Result as StdResult;
+
+pub use super::headers::Headers;
+use super::grammar;
+use super::util::{ErrorKind, ProtocolError};
+
 /// Enum representing various types of events in a communication system.
 ///
 /// Each variant corresponds to a different event type that can be handled, such as requests,
-/// responses, data transfers, or connection status changes.
+/// responses, data transfers, or connection status changes. The same events are produced by
+/// `Connection::next_event` and consumed by `Connection::send`.
 ///
 /// # Example
 /// ```rust
-/// let event = Event::Request(Request { /* fields */ });
+/// use yo::http1::events::{Event, Request};
+///
+/// let request = Request::new("GET", "/", vec![(b"host".to_vec(), b"example.com".to_vec())], "1.1").unwrap();
+/// let event = Event::Request(request);
 /// match event {
 ///     Event::Request(req) => println!("Handling request: {:?}", req),
 ///     _ => println!("Other event"),
 /// }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event {
     Request(Request),
     InformationalResponse(InformationalResponse),
@@ -23,44 +31,39 @@ pub enum Event {
     ConnectionClosed(ConnectionClosed),
 }
 
-/// A struct representing HTTP headers as a vector of key-value pairs (both as `Vec<u8>`).
+/// The kind of an [`Event`], without its payload.
+///
+/// The connection state machine is keyed on event types rather than on events themselves.
 ///
 /// # Example
 /// ```rust
-/// let headers = vec![(b"Content-Type".to_vec(), b"application/json".to_vec())];
-/// let result = Headers::normalize_and_validate(headers);
-/// match result {
-///     Ok(headers) => println!("Valid headers: {:?}", headers),
-///     Err(e) => println!("Error: {:?}", e),
-/// }
+/// use yo::http1::events::{Data, Event, EventType};
+///
+/// let event = Event::Data(Data::new(b"hi".to_vec(), false, false));
+/// assert_eq!(event.event_type(), EventType::Data);
 /// ```
-#[derive(Debug, Clone)]
-pub struct Headers(pub Vec<(Vec<u8>, Vec<u8>)>);
-
-impl Headers {
-    /// Normalizes and validates the given headers.
-    ///
-    /// # Arguments
-    /// * `headers` - A vector of key-value pairs representing HTTP headers.
-    ///
-    /// # Returns
-    /// Returns `Ok(Headers)` if validation passes, or an error if validation fails.
-    pub fn normalize_and_validate(headers: Vec<(Vec<u8>, Vec<u8>)>) -> StdResult<Self, LocalProtocolError> {
-        
-        Ok(Headers(headers))
-    }
-}
-
-#[derive(Debug)]
-pub struct LocalProtocolError(String);
-
-impl std::fmt::Display for LocalProtocolError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{}", self.0)
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventType {
+    Request,
+    InformationalResponse,
+    Response,
+    Data,
+    EndOfMessage,
+    ConnectionClosed,
 }
 
 impl Event {
+    /// Returns the [`EventType`] of this event.
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::Request(_) => EventType::Request,
+            Event::InformationalResponse(_) => EventType::InformationalResponse,
+            Event::Response(_) => EventType::Response,
+            Event::Data(_) => EventType::Data,
+            Event::EndOfMessage(_) => EventType::EndOfMessage,
+            Event::ConnectionClosed(_) => EventType::ConnectionClosed,
+        }
+    }
     /// Validates the HTTP method.
     ///
     /// # Arguments
@@ -68,9 +71,8 @@ impl Event {
     ///
     /// # Returns
     /// Returns `Ok(())` if the method is valid, or an error if validation fails.
-    pub fn validate_method(&self, method: &[u8]) -> StdResult<(), LocalProtocolError> {
-        
-        Ok(())
+    pub fn validate_method(&self, method: &[u8]) -> StdResult<(), ProtocolError> {
+        check_method(method)
     }
     /// Validates the target (e.g., URL path or resource).
     ///
@@ -79,13 +81,49 @@ impl Event {
     ///
     /// # Returns
     /// Returns `Ok(())` if the target is valid, or an error if validation fails.
-    pub fn validate_target(&self, target: &[u8]) -> StdResult<(), LocalProtocolError> {
-        
-        Ok(())
+    pub fn validate_target(&self, target: &[u8]) -> StdResult<(), ProtocolError> {
+        check_target(target)
+    }
+}
+
+/// Checks a method against the `token` grammar.
+fn check_method(method: &[u8]) -> StdResult<(), ProtocolError> {
+    if grammar::is_token(method) {
+        return Ok(());
+    }
+    let lossy = String::from_utf8_lossy(method);
+    Err(ProtocolError::local(ErrorKind::BadRequestLine, format!("Illegal method characters: {:?}", lossy)))
+}
+
+/// Checks a request target against the `request-target` grammar.
+fn check_target(target: &[u8]) -> StdResult<(), ProtocolError> {
+    if grammar::is_request_target(target) {
+        return Ok(());
     }
+    let lossy = String::from_utf8_lossy(target);
+    Err(ProtocolError::local(ErrorKind::BadRequestLine, format!("Illegal target characters: {:?}", lossy)))
 }
 
-#[derive(Debug)]
+/// An HTTP request head.
+///
+/// `http_version` holds just the version number, e.g. `1.1`.
+///
+/// # Example
+/// ```rust
+/// use yo::http1::events::Request;
+///
+/// let request = Request::new("GET", "/", vec![(b"Host".to_vec(), b"example.com".to_vec())], "1.1").unwrap();
+/// assert_eq!(request.target, b"/");
+///
+/// // HTTP/1.1 requests must carry exactly one Host header
+/// assert!(Request::new("GET", "/", vec![], "1.1").is_err());
+///
+/// // Methods are tokens, and targets can't contain whitespace
+/// let host = vec![(b"Host".to_vec(), b"example.com".to_vec())];
+/// assert!(Request::new("GE T", "/", host.clone(), "1.1").is_err());
+/// assert!(Request::new("GET", "/a b", host, "1.1").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Request {
     pub method: Vec<u8>,
     pub headers: Headers,
@@ -94,20 +132,48 @@ pub struct Request {
 }
 
 impl Request {
+    /// Creates a new `Request`, normalizes headers, and validates the HTTP version and headers.
+    ///
+    /// # Arguments
+    /// * `method` - HTTP method (e.g., "GET", "POST").
+    /// * `target` - The target URI (e.g., "/index.html").
+    /// * `headers` - A vector of key-value pairs representing HTTP headers.
+    /// * `http_version` - HTTP version (e.g., "1.1").
+    ///
+    /// # Returns
+    /// Returns `Ok(Request)` if creation and validation succeed, or an error if validation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the method or target is malformed, or if the `Host` header is missing
+    /// for HTTP/1.1, or repeated.
     pub fn new(
         method: impl Into<Vec<u8>>,
         target: impl Into<Vec<u8>>,
         headers: Vec<(Vec<u8>, Vec<u8>)>,
         http_version: impl Into<Vec<u8>>,
-    ) -> StdResult<Self, LocalProtocolError> {
-        let method = method.into();
-        let target = target.into();
-        let http_version = http_version.into();
-        let headers = Headers::normalize_and_validate(headers)?;
-
-        // Validation steps
-        if http_version == b"1.1" && !headers.0.iter().any(|(name, _)| name == b"host") {
-            return Err(LocalProtocolError("Missing mandatory Host: header".into()).into());
+    ) -> StdResult<Self, ProtocolError> {
+        let headers = Headers::normalize_and_validate(&headers, false)?;
+        Self::with_headers(method.into(), target.into(), headers, http_version.into())
+    }
+    /// Builds a `Request` from headers that have already been normalized, e.g. by the parser.
+    pub(crate) fn with_headers(
+        method: Vec<u8>,
+        target: Vec<u8>,
+        headers: Headers,
+        http_version: Vec<u8>,
+    ) -> StdResult<Self, ProtocolError> {
+        check_method(&method)?;
+        check_target(&target)?;
+        let host_count = headers
+            .full_items()
+            .iter()
+            .filter(|(_, name, _)| &name[..] == b"host")
+            .count();
+        if http_version == b"1.1" && host_count == 0 {
+            return Err(ProtocolError::local(ErrorKind::MissingHost, "Missing mandatory Host: header"));
+        }
+        if host_count > 1 {
+            return Err(ProtocolError::local(ErrorKind::DuplicateHost, "Found multiple Host: headers"));
         }
 
         Ok(Request {
@@ -117,9 +183,35 @@ impl Request {
             http_version,
         })
     }
+    /// Returns the transfer codings applied to the request body, in the order they were applied.
+    ///
+    /// # Example
+    /// ```rust
+    /// use yo::http1::events::Request;
+    ///
+    /// let headers = vec![
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"Transfer-Encoding".to_vec(), b"gzip, chunked".to_vec()),
+    /// ];
+    /// let request = Request::new("POST", "/", headers, "1.1").unwrap();
+    /// assert_eq!(request.transfer_codings(), vec![b"gzip".to_vec(), b"chunked".to_vec()]);
+    /// ```
+    pub fn transfer_codings(&self) -> Vec<Vec<u8>> {
+        self.headers.transfer_codings()
+    }
 }
 
-#[derive(Debug)]
+/// An informational (1xx) response head.
+///
+/// # Example
+/// ```rust
+/// use yo::http1::events::InformationalResponse;
+///
+/// let response = InformationalResponse::new(100, vec![], "Continue", "1.1").unwrap();
+/// assert_eq!(response.status_code, 100);
+/// assert!(InformationalResponse::new(200, vec![], "OK", "1.1").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InformationalResponse {
     pub status_code: u16,
     pub headers: Headers,
@@ -128,35 +220,41 @@ pub struct InformationalResponse {
 }
 
 impl InformationalResponse {
-    /// Creates a new `Request`, normalizes headers, and validates the HTTP version and headers.
+    /// Creates a new `InformationalResponse`, validates the status code, and normalizes headers.
     ///
     /// # Arguments
-    /// * `method` - HTTP method (e.g., "GET", "POST").
-    /// * `target` - The target URI (e.g., "/index.html").
+    /// * `status_code` - HTTP status code, in the range [100, 200).
     /// * `headers` - A vector of key-value pairs representing HTTP headers.
+    /// * `reason` - The reason phrase for the response (e.g., "Continue").
     /// * `http_version` - HTTP version (e.g., "1.1").
     ///
     /// # Returns
-    /// Returns `Ok(Request)` if creation and validation succeed, or an error if validation fails.
+    /// Returns `Ok(InformationalResponse)` if creation and validation succeed, or an error if validation fails.
     ///
     /// # Errors
-    /// Returns an error if the `Host` header is missing for HTTP/1.1.
+    /// Returns an error if the `status_code` is outside the range [100, 200).
     pub fn new(
         status_code: u16,
         headers: Vec<(Vec<u8>, Vec<u8>)>,
         reason: impl Into<Vec<u8>>,
         http_version: impl Into<Vec<u8>>,
-    ) -> StdResult<Self, LocalProtocolError> {
+    ) -> StdResult<Self, ProtocolError> {
+        let headers = Headers::normalize_and_validate(&headers, false)?;
+        Self::with_headers(status_code, headers, reason.into(), http_version.into())
+    }
+    /// Builds an `InformationalResponse` from headers that have already been normalized.
+    pub(crate) fn with_headers(
+        status_code: u16,
+        headers: Headers,
+        reason: Vec<u8>,
+        http_version: Vec<u8>,
+    ) -> StdResult<Self, ProtocolError> {
         if !(100..200).contains(&status_code) {
-            return Err(LocalProtocolError(format!(
+            return Err(ProtocolError::local(ErrorKind::BadStatusCode, format!(
                 "InformationalResponse status_code should be in range [100, 200), not {}",
                 status_code
-            ))
-            .into());
+            )));
         }
-        let reason = reason.into();
-        let http_version = http_version.into();
-        let headers = Headers::normalize_and_validate(headers)?;
         Ok(InformationalResponse {
             status_code,
             headers,
@@ -166,7 +264,16 @@ impl InformationalResponse {
     }
 }
 
-#[derive(Debug)]
+/// A final (2xx-9xx) response head.
+///
+/// # Example
+/// ```rust
+/// use yo::http1::events::Response;
+///
+/// let response = Response::new(200, vec![(b"Content-Length".to_vec(), b"0".to_vec())], "OK", "1.1").unwrap();
+/// assert_eq!(response.headers.get_comma_header(b"content-length"), vec![b"0".to_vec()]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Response {
     pub status_code: u16,
     pub headers: Headers,
@@ -193,17 +300,23 @@ impl Response {
         headers: Vec<(Vec<u8>, Vec<u8>)>,
         reason: impl Into<Vec<u8>>,
         http_version: impl Into<Vec<u8>>,
-    ) -> StdResult<Self, LocalProtocolError> {
+    ) -> StdResult<Self, ProtocolError> {
+        let headers = Headers::normalize_and_validate(&headers, false)?;
+        Self::with_headers(status_code, headers, reason.into(), http_version.into())
+    }
+    /// Builds a `Response` from headers that have already been normalized.
+    pub(crate) fn with_headers(
+        status_code: u16,
+        headers: Headers,
+        reason: Vec<u8>,
+        http_version: Vec<u8>,
+    ) -> StdResult<Self, ProtocolError> {
         if !(200..1000).contains(&status_code) {
-            return Err(LocalProtocolError(format!(
+            return Err(ProtocolError::local(ErrorKind::BadStatusCode, format!(
                 "Response status_code should be in range [200, 1000), not {}",
                 status_code
-            ))
-            .into());
+            )));
         }
-        let reason = reason.into();
-        let http_version = http_version.into();
-        let headers = Headers::normalize_and_validate(headers)?;
         Ok(Response {
             status_code,
             headers,
@@ -211,11 +324,102 @@ impl Response {
             http_version,
         })
     }
+    /// Returns the transfer codings applied to the response body, in the order they were applied.
+    ///
+    /// Only `chunked` is undone by the connection, so a body coded with anything else arrives as
+    /// is.
+    ///
+    /// # Example
+    /// ```rust
+    /// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Request, Role};
+    ///
+    /// let mut connection = Connection::new(Role::Client, 16 * 1024);
+    /// let request = Request::new("GET", "/", vec![(b"Host".to_vec(), b"a".to_vec())], "1.1").unwrap();
+    /// connection.send(Event::Request(request)).unwrap();
+    /// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+    ///
+    /// connection.receive_data(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n3\r\n\x1f\x8b\x08\r\n0\r\n\r\n").unwrap();
+    /// match connection.next_event() {
+    ///     Ok(NextEvent::Event(Event::Response(response))) => {
+    ///         assert_eq!(response.transfer_codings(), vec![b"gzip".to_vec(), b"chunked".to_vec()]);
+    ///     }
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(d))) if &d.data[..] == b"\x1f\x8b\x08"));
+    /// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+    /// ```
+    pub fn transfer_codings(&self) -> Vec<Vec<u8>> {
+        self.headers.transfer_codings()
+    }
 }
 
-#[derive(Debug)]
+/// The bytes of a message body, as carried by `Data`, and of the header fields in `Headers`.
+///
+/// This is a plain `Vec<u8>` by default. With the `bytes` feature it is `bytes::Bytes`, and the
+/// bodies and header fields `Connection::next_event` hands out share the receive buffer's
+/// allocation instead of being copied out of it, so a proxy can pass large messages along
+/// without copying them. The fields of the start line are short and are still copied.
+#[cfg(not(feature = "bytes"))]
+pub type Payload = Vec<u8>;
+/// The bytes of a message body, as carried by `Data`, and of the header fields in `Headers`.
+///
+/// This is a plain `Vec<u8>` by default. With the `bytes` feature it is `bytes::Bytes`, and the
+/// bodies and header fields `Connection::next_event` hands out share the receive buffer's
+/// allocation instead of being copied out of it, so a proxy can pass large messages along
+/// without copying them. The fields of the start line are short and are still copied.
+#[cfg(feature = "bytes")]
+pub type Payload = bytes::Bytes;
+
+/// Turns owned bytes into a `Payload` without copying them.
+#[cfg(not(feature = "bytes"))]
+pub(crate) fn into_payload(bytes: Vec<u8>) -> Payload {
+    bytes
+}
+/// Turns owned bytes into a `Payload` without copying them.
+#[cfg(feature = "bytes")]
+pub(crate) fn into_payload(bytes: Vec<u8>) -> Payload {
+    bytes::Bytes::from(bytes)
+}
+
+/// Turns a `Payload` back into owned bytes, copying only if the allocation is shared.
+#[cfg(not(feature = "bytes"))]
+pub(crate) fn payload_into_vec(payload: Payload) -> Vec<u8> {
+    payload
+}
+/// Turns a `Payload` back into owned bytes, copying only if the allocation is shared.
+#[cfg(feature = "bytes")]
+pub(crate) fn payload_into_vec(payload: Payload) -> Vec<u8> {
+    Vec::from(payload)
+}
+
+/// Returns `part`, which has to lie within `whole`, as a `Payload` of its own: a copy by
+/// default, or a view into `whole`'s allocation with the `bytes` feature.
+#[cfg(not(feature = "bytes"))]
+pub(crate) fn share_payload(_whole: &Payload, part: &[u8]) -> Payload {
+    part.to_vec()
+}
+/// Returns `part`, which has to lie within `whole`, as a `Payload` of its own: a copy by
+/// default, or a view into `whole`'s allocation with the `bytes` feature.
+#[cfg(feature = "bytes")]
+pub(crate) fn share_payload(whole: &Payload, part: &[u8]) -> Payload {
+    whole.slice_ref(part)
+}
+
+/// A piece of a message body.
+///
+/// `chunk_start` and `chunk_end` mark the boundaries of chunks in a chunked body, for callers
+/// that care about how the peer split it up.
+///
+/// # Example
+/// ```rust
+/// use yo::http1::events::Data;
+///
+/// let data = Data::new(b"hello".to_vec(), false, false);
+/// assert_eq!(&data.data[..], b"hello");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Data {
-    pub data: Vec<u8>,
+    pub data: Payload,
     pub chunk_start: bool,
     pub chunk_end: bool,
 }
@@ -224,22 +428,32 @@ impl Data {
     /// Creates a new `Data` instance with the given chunk flags.
     ///
     /// # Arguments
-    /// * `data` - The chunk data.
+    /// * `data` - The chunk data, as a `Vec<u8>` or anything else that converts into a `Payload`.
     /// * `chunk_start` - Whether this chunk is the start of a series.
     /// * `chunk_end` - Whether this chunk is the end of a series.
     ///
     /// # Returns
     /// A new `Data` instance.
-    pub fn new(data: Vec<u8>, chunk_start: bool, chunk_end: bool) -> Self {
+    pub fn new(data: impl Into<Payload>, chunk_start: bool, chunk_end: bool) -> Self {
         Data {
-            data,
+            data: data.into(),
             chunk_start,
             chunk_end,
         }
     }
 }
 
-#[derive(Debug)]
+/// The end of a message body, with any trailer fields.
+///
+/// # Example
+/// ```rust
+/// use yo::http1::events::EndOfMessage;
+///
+/// let eom = EndOfMessage::new(Some(vec![(b"Expires".to_vec(), b"never".to_vec())])).unwrap();
+/// assert_eq!(eom.headers.raw_items().len(), 1);
+/// assert_eq!(EndOfMessage::default(), EndOfMessage::new(None).unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct EndOfMessage {
     pub headers: Headers,
 }
@@ -251,17 +465,18 @@ impl EndOfMessage {
     /// * `headers` - Optional vector of key-value pairs representing headers.
     ///
     /// # Returns
-    /// A new `EndOfMessage` instance with normalized headers.
-    pub fn new(headers: Option<Vec<(Vec<u8>, Vec<u8>)>>) -> Self {
+    /// A new `EndOfMessage` instance with normalized headers, or an error if they are invalid.
+    pub fn new(headers: Option<Vec<(Vec<u8>, Vec<u8>)>>) -> StdResult<Self, ProtocolError> {
         let headers = match headers {
-            Some(h) => Headers::normalize_and_validate(h).unwrap(),
-            None => Headers(vec![]),
+            Some(h) => Headers::normalize_and_validate(&h, false)?,
+            None => Headers::default(),
         };
-        EndOfMessage { headers }
+        Ok(EndOfMessage { headers })
     }
 }
 
-#[derive(Debug)]
+/// The peer closed its side of the connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ConnectionClosed;
 
 impl ConnectionClosed {
@@ -273,5 +488,3 @@ impl ConnectionClosed {
         ConnectionClosed
     }
 }
-
-
@@ -0,0 +1,311 @@
+use super::connection::Role;
+use super::headers::Headers;
+use super::util::{ErrorKind, ProtocolError};
+
+/// The transfer codings registered with IANA. Anything else is refused rather than passed on,
+/// since whatever sits behind us may not agree on what it means.
+pub const KNOWN_TRANSFER_CODINGS: &[&[u8]] = &[b"chunked", b"compress", b"deflate", b"gzip", b"x-compress", b"x-gzip"];
+
+/// How the body of a message is delimited on the wire, as worked out by [`body_framing`].
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::framing::Framing;
+///
+/// assert_ne!(Framing::NoBody, Framing::ContentLength(0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// The message can't have a body, whatever its headers say.
+    NoBody,
+    /// The body is exactly this many bytes long.
+    ContentLength(usize),
+    /// The body uses the chunked transfer coding.
+    Chunked,
+    /// The body runs until the sender closes the connection. Only responses are framed this way.
+    ReadUntilClose,
+}
+
+/// Decides how the body of a message is framed, following RFC 9112 section 6.3.
+///
+/// Connections use this both for what they receive and for what they send, so the two sides can
+/// never disagree about where a message ends.
+///
+/// # Arguments
+/// * `role` - Who sent the message: a `Client` sends requests, a `Server` responses.
+/// * `request_method` - The method of the request, or for a response, of the request it answers.
+/// * `status_code` - The response status code; ignored for requests.
+/// * `headers` - The message headers.
+/// * `http_version` - The HTTP version of the message, e.g. `1.1`.
+///
+/// # Returns
+/// The framing of the body, or an error if the framing headers can't be trusted.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::connection::Role;
+/// use yo::http1::framing::{body_framing, Framing};
+/// use yo::http1::headers::Headers;
+///
+/// let none = Headers::default();
+/// let chunked = Headers::normalize_and_validate(&[(b"Transfer-Encoding".to_vec(), b"chunked".to_vec())], false).unwrap();
+/// let sized = Headers::normalize_and_validate(&[(b"Content-Length".to_vec(), b"10".to_vec())], false).unwrap();
+///
+/// // Responses to HEAD, 204 and 304 responses, and 2xx answers to CONNECT have no body
+/// assert_eq!(body_framing(Role::Server, b"HEAD", 200, &sized, b"1.1").unwrap(), Framing::NoBody);
+/// assert_eq!(body_framing(Role::Server, b"GET", 304, &sized, b"1.1").unwrap(), Framing::NoBody);
+/// assert_eq!(body_framing(Role::Server, b"CONNECT", 200, &chunked, b"1.1").unwrap(), Framing::NoBody);
+/// assert_eq!(body_framing(Role::Server, b"CONNECT", 407, &sized, b"1.1").unwrap(), Framing::ContentLength(10));
+///
+/// // Transfer-Encoding wins over Content-Length
+/// assert_eq!(body_framing(Role::Server, b"GET", 200, &chunked, b"1.1").unwrap(), Framing::Chunked);
+///
+/// // Only a final chunked coding frames the body; a request has to end its codings with it
+/// let gzip_chunked = Headers::normalize_and_validate(&[(b"Transfer-Encoding".to_vec(), b"gzip, chunked".to_vec())], false).unwrap();
+/// let gzip = Headers::normalize_and_validate(&[(b"Transfer-Encoding".to_vec(), b"gzip".to_vec())], false).unwrap();
+/// assert_eq!(body_framing(Role::Client, b"POST", 0, &gzip_chunked, b"1.1").unwrap(), Framing::Chunked);
+/// assert_eq!(body_framing(Role::Server, b"GET", 200, &gzip, b"1.1").unwrap(), Framing::ReadUntilClose);
+/// assert!(body_framing(Role::Client, b"POST", 0, &gzip, b"1.1").is_err());
+///
+/// // Without either header, a request has no body and a response runs until the connection closes
+/// assert_eq!(body_framing(Role::Client, b"POST", 0, &none, b"1.1").unwrap(), Framing::NoBody);
+/// assert_eq!(body_framing(Role::Server, b"GET", 200, &none, b"1.0").unwrap(), Framing::ReadUntilClose);
+///
+/// // An HTTP/1.0 message can't really be chunked, so the response is read until close and the
+/// // request is refused
+/// assert_eq!(body_framing(Role::Server, b"GET", 200, &chunked, b"1.0").unwrap(), Framing::ReadUntilClose);
+/// assert!(body_framing(Role::Client, b"POST", 0, &chunked, b"1.0").is_err());
+/// ```
+///
+/// A connection applies the same rules to what it receives, remembering which request each
+/// response answers:
+///
+/// ```rust
+/// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Request, Role};
+///
+/// let mut connection = Connection::new(Role::Client, 16 * 1024);
+/// let request = Request::new(b"HEAD".to_vec(), b"/".to_vec(), vec![(b"Host".to_vec(), b"a".to_vec())], b"1.1".to_vec()).unwrap();
+/// connection.send(Event::Request(request)).unwrap();
+/// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+///
+/// connection.receive_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n").unwrap();
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Response(_)))));
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+/// ```
+pub fn body_framing(
+    role: Role,
+    request_method: &[u8],
+    status_code: u16,
+    headers: &Headers,
+    http_version: &[u8],
+) -> Result<Framing, ProtocolError> {
+    validate_framing(headers)?;
+    if role == Role::Server
+        && (status_code < 200
+            || status_code == 204
+            || status_code == 304
+            || request_method == b"HEAD"
+            || (request_method == b"CONNECT" && (200..300).contains(&status_code)))
+    {
+        return Ok(Framing::NoBody);
+    }
+
+    let codings = headers.transfer_codings();
+    if let Some(last_coding) = codings.last() {
+        // Transfer-Encoding only exists since HTTP/1.1, so in an older message it most likely
+        // passed through something that didn't decode it, and the framing is faulty
+        if http_version < &b"1.1"[..] {
+            return match role {
+                Role::Client => Err(ProtocolError::local(
+                    ErrorKind::UnsupportedTransferEncoding,
+                    "Transfer-Encoding in an HTTP/1.0 request",
+                )),
+                Role::Server => Ok(Framing::ReadUntilClose),
+            };
+        }
+        if last_coding == b"chunked" {
+            return Ok(Framing::Chunked);
+        }
+        // Only chunked says where the body ends: a request must apply it last, and a response
+        // that doesn't is delimited by closing the connection
+        return match role {
+            Role::Client => Err(ProtocolError::local(
+                ErrorKind::UnsupportedTransferEncoding,
+                "chunked must be the final transfer coding of a request",
+            )),
+            Role::Server => Ok(Framing::ReadUntilClose),
+        };
+    }
+
+    if let Some(length) = headers.get_comma_header(b"content-length").first() {
+        let length = std::str::from_utf8(length)
+            .ok()
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| ProtocolError::local(ErrorKind::BadContentLength, "Content-Length too large"))?;
+        return Ok(Framing::ContentLength(length));
+    }
+
+    Ok(match role {
+        Role::Client => Framing::NoBody,
+        Role::Server => Framing::ReadUntilClose,
+    })
+}
+
+/// Checks the framing headers of a message against the tricks used to smuggle one request
+/// inside another, past a proxy that reads the framing differently than the server behind it.
+///
+/// `body_framing` runs this on every message, sent or received. The policy is strict: besides
+/// what `Headers` already refuses (differing `Content-Length`s, `chunked` applied twice) it
+/// rejects
+/// * a message carrying both `Content-Length` and `Transfer-Encoding`,
+/// * transfer codings that aren't in [`KNOWN_TRANSFER_CODINGS`], or that carry parameters,
+/// * header names like `Transfer_Encoding` that some servers take for a framing header.
+///
+/// Folded framing headers and malformed chunk-size lines are refused by the readers.
+///
+/// # Arguments
+/// * `headers` - The message headers.
+///
+/// # Returns
+/// `Ok(())`, or an error saying what could be misread.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::framing::validate_framing;
+/// use yo::http1::headers::Headers;
+/// use yo::http1::util::ErrorKind;
+///
+/// let both = Headers::normalize_and_validate(&[
+///     (b"Content-Length".to_vec(), b"5".to_vec()),
+///     (b"Transfer-Encoding".to_vec(), b"chunked".to_vec()),
+/// ], false).unwrap();
+/// assert_eq!(validate_framing(&both).unwrap_err().kind(), ErrorKind::AmbiguousFraming);
+///
+/// let unknown = Headers::normalize_and_validate(&[(b"Transfer-Encoding".to_vec(), b"xchunked".to_vec())], false).unwrap();
+/// assert_eq!(validate_framing(&unknown).unwrap_err().error_status_hint(), 501);
+/// ```
+///
+/// Every known desync pattern is rejected by a server connection, while the legitimate
+/// variations next to them still go through:
+///
+/// ```rust
+/// use yo::http1::{Connection, Event, NextEvent, Role};
+///
+/// fn rejected(payload: &[u8]) -> bool {
+///     let mut connection = Connection::new(Role::Server, 16 * 1024);
+///     connection.receive_data(payload).unwrap();
+///     loop {
+///         match connection.next_event() {
+///             Err(_) => return true,
+///             Ok(NextEvent::Event(Event::EndOfMessage(_))) | Ok(NextEvent::Sentinel(_)) => return false,
+///             Ok(_) => {}
+///         }
+///     }
+/// }
+///
+/// let head = "POST / HTTP/1.1\r\nHost: a\r\n";
+/// let smuggling: &[&str] = &[
+///     // CL.TE and TE.CL
+///     "Content-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG",
+///     "Transfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+///     "Content-Length: 0\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+///     // TE.TE: obfuscated codings that some servers ignore and others honour
+///     "Transfer-Encoding: xchunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked-false\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked;q=1\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: \"chunked\"\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: identity, chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked, chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked, gzip\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: gzip\r\n\r\n",
+///     "Transfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding\t: chunked\r\n\r\n0\r\n\r\n",
+///     " Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+///     "X: y\r\n Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\x0b\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: \x0cchunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\rX: y\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: ch\x00unked\r\n\r\n0\r\n\r\n",
+///     "Transfer_Encoding: chunked\r\n\r\n0\r\n\r\n",
+///     "Content_Length: 5\r\n\r\nhello",
+///     // CL.CL
+///     "Content-Length: 5\r\nContent-Length: 6\r\n\r\nhello!",
+///     "Content-Length: 5, 6\r\n\r\nhello!",
+///     "Content-Length: +5\r\n\r\nhello",
+///     "Content-Length: 0x5\r\n\r\nhello",
+///     "Content-Length: 5 5\r\n\r\nhello",
+///     "Content-Length: 99999999999999999999999\r\n\r\n",
+///     "Content-Length:\r\n 5\r\n\r\nhello",
+///     // Chunk-size lines the front and back ends could split differently
+///     "Transfer-Encoding: chunked\r\n\r\n5\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5;a\rb\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5;\nxx\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n0x5\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n-5\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n00000000000000005\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n10000000000000000\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhelloXX0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhello\n0\r\n\r\n",
+///     // Framing and routing fields in a trailer, for anything that merges it into the head
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\nContent-Length: 5\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\nTransfer-Encoding: chunked\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n0\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n0\r\nhost: b\r\n\r\n",
+/// ];
+/// for payload in smuggling {
+///     assert!(rejected(format!("{}{}", head, payload).as_bytes()), "accepted {:?}", payload);
+/// }
+/// // HTTP/1.0 has no Transfer-Encoding
+/// assert!(rejected(b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"));
+///
+/// let legitimate: &[&str] = &[
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding:\tCHUNKED \r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5;name=\"a b\" \r\nhello\r\n0000000000000000\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5 ;a\r\nhello\r\n0\r\n\r\n",
+///     "Transfer-Encoding: chunked\r\n\r\n5\t; a = b ;c\r\nhello\r\n0\r\n\r\n",
+///     "Content-Length: 5\r\nContent-Length: 5\r\n\r\nhello",
+///     "Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\nExpires: never\r\n\r\n",
+/// ];
+/// for payload in legitimate {
+///     assert!(!rejected(format!("{}{}", head, payload).as_bytes()), "rejected {:?}", payload);
+/// }
+/// ```
+pub fn validate_framing(headers: &Headers) -> Result<(), ProtocolError> {
+    for (name, _) in headers.iter() {
+        if !name.contains(&b'_') {
+            continue;
+        }
+        let dashed: Vec<u8> = name.iter().map(|&b| if b == b'_' { b'-' } else { b.to_ascii_lowercase() }).collect();
+        if dashed == b"content-length" || dashed == b"transfer-encoding" {
+            return Err(ProtocolError::local(
+                ErrorKind::AmbiguousFraming,
+                format!("{:?} could be taken for a framing header", String::from_utf8_lossy(name)),
+            ));
+        }
+    }
+
+    let codings = headers.transfer_codings();
+    if !codings.is_empty() && headers.contains(b"content-length") {
+        return Err(ProtocolError::local(
+            ErrorKind::AmbiguousFraming,
+            "both Content-Length and Transfer-Encoding",
+        ));
+    }
+    if let Some(coding) = codings.iter().find(|coding| !KNOWN_TRANSFER_CODINGS.contains(&&coding[..])) {
+        return Err(ProtocolError::new_local(
+            ErrorKind::UnsupportedTransferEncoding,
+            format!("unknown transfer coding {:?}", String::from_utf8_lossy(coding)),
+            501,
+        ));
+    }
+
+    Ok(())
+}
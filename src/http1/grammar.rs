@@ -0,0 +1,61 @@
+//! The HTTP/1.1 grammar as the rest of the crate sees it.
+//!
+//! Two interchangeable backends implement the functions re-exported here: the regular
+//! expressions in `http_regex` and the hand-written byte scanners in `scanner`. Both accept
+//! exactly the same inputs. The scanners are used when the `scanner` feature is on, or when the
+//! crate is built without the `regex` feature; otherwise the regexes are.
+//!
+//! # Example
+//!
+//! ```rust
+//! use yo::http1::grammar;
+//!
+//! let line = grammar::parse_request_line(b"GET /index.html HTTP/1.1").unwrap();
+//! assert_eq!(line.method, b"GET");
+//! assert_eq!(line.target, b"/index.html");
+//! assert_eq!(line.http_version, b"1.1");
+//!
+//! let field = grammar::parse_header_field(b"Host:  example.com ").unwrap();
+//! assert_eq!(field.name, b"Host");
+//! assert_eq!(field.value, b"example.com");
+//!
+//! assert!(grammar::parse_status_line(b"HTTP/1.1 2000 OK").is_none());
+//! ```
+
+#[cfg(any(feature = "scanner", not(feature = "regex")))]
+pub use super::scanner::{
+    is_content_length, is_field_value, is_request_target, is_token, parse_chunk_header,
+    parse_header_field, parse_request_line, parse_status_line,
+};
+#[cfg(all(feature = "regex", not(feature = "scanner")))]
+pub use super::http_regex::{
+    is_content_length, is_field_value, is_request_target, is_token, parse_chunk_header,
+    parse_header_field, parse_request_line, parse_status_line,
+};
+
+/// The pieces of a request line, borrowed from the line itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLine<'a> {
+    pub method: &'a [u8],
+    pub target: &'a [u8],
+    /// The version without its `HTTP/` prefix, e.g. `1.1`.
+    pub http_version: &'a [u8],
+}
+
+/// The pieces of a status line, borrowed from the line itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusLine<'a> {
+    /// The version without its `HTTP/` prefix, e.g. `1.1`.
+    pub http_version: &'a [u8],
+    pub status_code: u16,
+    /// The reason phrase, empty if the line had none.
+    pub reason: &'a [u8],
+}
+
+/// A header line split into its name and its value, with the whitespace around the value
+/// removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderField<'a> {
+    pub name: &'a [u8],
+    pub value: &'a [u8],
+}
@@ -1,15 +1,43 @@
-use std::str;
 use std::vec::Vec;
-use regex::Regex;
+
+use super::events::{into_payload, payload_into_vec, share_payload, Payload};
+use super::grammar;
+use super::typed_headers::TypedHeader;
+use super::util::{ErrorKind, ProtocolError};
 
 /// It is the Headers struct definition
-#[derive(Debug, PartialEq, Eq, Clone)]
+///
+/// This is the one header type shared by every event, reader and writer in the crate.
+///
+/// Fields are stored as `Payload`s, so with the `bytes` feature the headers of a received
+/// message are views into the receive buffer rather than copies of it.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{Reader, RequestHeadReader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+/// let head = buffer.as_bytes().as_ptr();
+/// let request = match RequestHeadReader::default().read(&mut buffer).unwrap() {
+///     Some(Event::Request(request)) => request,
+///     other => panic!("unexpected {:?}", other),
+/// };
+/// let host = request.headers.get(b"host").unwrap();
+/// assert_eq!(host, b"example.com");
+/// // With the `bytes` feature, the value is where it was received rather than a copy
+/// #[cfg(feature = "bytes")]
+/// assert_eq!(host.as_ptr(), head.wrapping_add(b"GET / HTTP/1.1\r\nHost: ".len()));
+/// # let _ = head;
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Headers {
-    full_items: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>, // (raw_name, lower_name, value)
+    full_items: Vec<(Payload, Payload, Payload)>, // (raw_name, lower_name, value)
 }
 
-#[derive(Debug)]
-pub struct LocalProtocolError(String);
 
 impl Headers {
     /// Creates a new `Headers` instance with the provided header items.
@@ -20,86 +48,360 @@ impl Headers {
     /// # Returns
     /// A new `Headers` instance.
     pub fn new(full_items: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>) -> Self {
+        let full_items = full_items
+            .into_iter()
+            .map(|(raw_name, lower_name, value)| (into_payload(raw_name), into_payload(lower_name), into_payload(value)))
+            .collect();
         Headers { full_items }
     }
     /// Returns a vector of raw key-value pairs from the headers.
     ///
+    /// Every pair is copied; `iter` borrows them instead.
+    ///
     /// # Returns
     /// A vector of tuples containing raw header name and value.
     pub fn raw_items(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.full_items.iter().map(|(raw_name, _, value)| (raw_name.to_vec(), value.to_vec())).collect()
+    }
+    /// Iterates over the raw name and value of every header in wire order, without copying.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[(b"Host".to_vec(), b"example.com".to_vec())], false).unwrap();
+    /// let items: Vec<(&[u8], &[u8])> = headers.iter().collect();
+    /// assert_eq!(items, vec![(&b"Host"[..], &b"example.com"[..])]);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> + '_ {
+        self.full_items.iter().map(|(raw_name, _, value)| (&raw_name[..], &value[..]))
+    }
+    /// Returns the `(raw_name, lower_name, value)` triples in wire order.
+    pub(crate) fn full_items(&self) -> &[(Payload, Payload, Payload)] {
+        &self.full_items
+    }
+    /// Returns the number of header fields, counting repeated names once per field.
+    pub fn len(&self) -> usize {
+        self.full_items.len()
+    }
+    /// Checks whether there are no header fields at all.
+    pub fn is_empty(&self) -> bool {
+        self.full_items.is_empty()
+    }
+    /// Checks whether a header is present, ignoring the case of `name`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[(b"Host".to_vec(), b"example.com".to_vec())], false).unwrap();
+    /// assert!(headers.contains(b"HOST"));
+    /// assert!(!headers.contains(b"accept"));
+    /// ```
+    pub fn contains(&self, name: &[u8]) -> bool {
+        self.get(name).is_some()
+    }
+    /// Returns the value of the first field called `name`, ignoring case.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[
+    ///     (b"Accept".to_vec(), b"text/html".to_vec()),
+    ///     (b"accept".to_vec(), b"*/*".to_vec()),
+    /// ], false).unwrap();
+    /// assert_eq!(headers.get(b"accept"), Some(&b"text/html"[..]));
+    /// assert_eq!(headers.get(b"host"), None);
+    /// ```
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.full_items
+            .iter()
+            .find(|(_, lower_name, _)| lower_name.eq_ignore_ascii_case(name))
+            .map(|(_, _, value)| &value[..])
+    }
+    /// Iterates over the values of every field called `name` in wire order, ignoring case.
+    ///
+    /// Unlike `get_comma_header`, values are neither split on commas nor lower-cased.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[
+    ///     (b"Set-Cookie".to_vec(), b"a=1, b=2".to_vec()),
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"set-cookie".to_vec(), b"C=3".to_vec()),
+    /// ], false).unwrap();
+    /// let cookies: Vec<&[u8]> = headers.get_all(b"Set-Cookie").collect();
+    /// assert_eq!(cookies, vec![&b"a=1, b=2"[..], &b"C=3"[..]]);
+    /// ```
+    pub fn get_all<'a>(&'a self, name: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.full_items
+            .iter()
+            .filter(move |(_, lower_name, _)| lower_name.eq_ignore_ascii_case(name))
+            .map(|(_, _, value)| &value[..])
+    }
+    /// Sets a header to a single value, replacing every field already called `name`.
+    ///
+    /// The new field takes the place of the first one it replaces, or goes last if there was
+    /// none, and keeps the casing of `name` as given. The whole header list is validated again,
+    /// and left untouched if that fails.
+    ///
+    /// # Arguments
+    /// * `name` - The header name, in the casing it should be sent with.
+    /// * `value` - The new value.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an error if the name or value is illegal or the framing headers conflict.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let mut headers = Headers::normalize_and_validate(&[
+    ///     (b"accept".to_vec(), b"text/html".to_vec()),
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"Accept".to_vec(), b"*/*".to_vec()),
+    /// ], false).unwrap();
+    /// headers.insert(b"ACCEPT", b"application/json").unwrap();
+    /// assert_eq!(headers.raw_items(), vec![
+    ///     (b"ACCEPT".to_vec(), b"application/json".to_vec()),
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    /// ]);
+    ///
+    /// assert!(headers.insert(b"Host", b"bad\r\nvalue").is_err());
+    /// assert_eq!(headers.get(b"host"), Some(&b"example.com"[..]));
+    /// ```
+    pub fn insert(&mut self, name: &[u8], value: &[u8]) -> Result<(), ProtocolError> {
+        let mut items = Vec::with_capacity(self.full_items.len() + 1);
+        let mut new_item = Some((into_payload(name.to_vec()), into_payload(value.to_vec())));
+        for (raw_name, lower_name, found_value) in &self.full_items {
+            if !lower_name.eq_ignore_ascii_case(name) {
+                items.push((raw_name.clone(), found_value.clone()));
+            } else if let Some(item) = new_item.take() {
+                items.push(item);
+            }
+        }
+        items.extend(new_item);
+        self.revalidate(items)
+    }
+    /// Adds a field after all the existing ones, keeping any already called `name`.
+    ///
+    /// # Arguments
+    /// * `name` - The header name, in the casing it should be sent with.
+    /// * `value` - The value of the new field.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an error if the name or value is illegal or the framing headers conflict.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let mut headers = Headers::default();
+    /// headers.append(b"Vary", b"Accept").unwrap();
+    /// headers.append(b"vary", b"Origin").unwrap();
+    /// assert_eq!(headers.len(), 2);
+    /// assert_eq!(headers.get_comma_header(b"vary"), vec![b"accept".to_vec(), b"origin".to_vec()]);
+    ///
+    /// // A second, different Content-Length is refused
+    /// headers.append(b"Content-Length", b"10").unwrap();
+    /// assert!(headers.append(b"Content-Length", b"11").is_err());
+    /// assert_eq!(headers.len(), 3);
+    /// ```
+    pub fn append(&mut self, name: &[u8], value: &[u8]) -> Result<(), ProtocolError> {
+        let mut items = self.pairs();
+        items.push((into_payload(name.to_vec()), into_payload(value.to_vec())));
+        self.revalidate(items)
+    }
+    /// Removes every field called `name`, ignoring case.
+    ///
+    /// # Returns
+    /// The values of the removed fields in wire order, empty if there were none.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let mut headers = Headers::normalize_and_validate(&[
+    ///     (b"Via".to_vec(), b"1.1 a".to_vec()),
+    ///     (b"Host".to_vec(), b"example.com".to_vec()),
+    ///     (b"via".to_vec(), b"1.1 b".to_vec()),
+    /// ], false).unwrap();
+    /// assert_eq!(headers.remove(b"VIA"), vec![b"1.1 a".to_vec(), b"1.1 b".to_vec()]);
+    /// assert_eq!(headers.raw_items(), vec![(b"Host".to_vec(), b"example.com".to_vec())]);
+    /// ```
+    pub fn remove(&mut self, name: &[u8]) -> Vec<Vec<u8>> {
+        let (removed, kept) = std::mem::take(&mut self.full_items)
+            .into_iter()
+            .partition(|(_, lower_name, _)| lower_name.eq_ignore_ascii_case(name));
+        self.full_items = kept;
+        removed.into_iter().map(|(_, _, value)| payload_into_vec(value)).collect()
+    }
+    /// Parses a typed header from every field called `H::NAME`.
+    ///
+    /// # Returns
+    /// `Ok(None)` if the header is absent, or an error naming the header if it doesn't parse.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    /// use yo::http1::typed_headers::Vary;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[(b"Vary".to_vec(), b"Origin".to_vec())], false).unwrap();
+    /// assert_eq!(headers.typed::<Vary>().unwrap(), Some(Vary::Fields(vec![b"origin".to_vec()])));
+    /// ```
+    pub fn typed<H: TypedHeader>(&self) -> Result<Option<H>, ProtocolError> {
+        let values: Vec<&[u8]> = self.get_all(H::NAME).collect();
+        if values.is_empty() {
+            return Ok(None);
+        }
+        H::parse(&values).map(Some)
+    }
+    /// Sets a typed header, replacing every field called `H::NAME` with one holding its
+    /// formatted value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    /// use yo::http1::typed_headers::ContentLength;
+    ///
+    /// let mut headers = Headers::default();
+    /// headers.set_typed(&ContentLength(5)).unwrap();
+    /// assert_eq!(headers.raw_items(), vec![(b"Content-Length".to_vec(), b"5".to_vec())]);
+    /// ```
+    pub fn set_typed<H: TypedHeader>(&mut self, header: &H) -> Result<(), ProtocolError> {
+        self.insert(H::NAME, &header.format())
+    }
+    /// Returns the raw name and value of every field, sharing rather than copying them with the
+    /// `bytes` feature.
+    fn pairs(&self) -> Vec<(Payload, Payload)> {
         self.full_items.iter().map(|(raw_name, _, value)| (raw_name.clone(), value.clone())).collect()
     }
+    /// Replaces the fields with `items` if they pass validation, and leaves them alone if not.
+    fn revalidate(&mut self, items: Vec<(Payload, Payload)>) -> Result<(), ProtocolError> {
+        *self = Self::normalize(items, false)?;
+        Ok(())
+    }
     /// Normalizes and validates the provided headers.
     ///
+    /// Values are opaque bytes: besides visible ASCII they may carry obs-text (0x80-0xFF),
+    /// which is how Latin-1 text still turns up in the wild.
+    ///
     /// # Arguments
     /// * `headers` - A slice of key-value pairs representing headers.
     /// * `parsed` - Flag indicating if the headers are already parsed.
     ///
     /// # Returns
     /// A validated `Headers` instance, or an error if validation fails.
-    pub fn normalize_and_validate(headers: &[(Vec<u8>, Vec<u8>)], parsed: bool) -> Result<Headers, LocalProtocolError> {
-        let mut new_headers: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> = Vec::new();
-        let mut seen_content_length: Option<Vec<u8>> = None;
-        let mut saw_transfer_encoding = false;
-
-        let content_length_re = Regex::new(r"^[0-9]+$").unwrap();
-        let field_name_re = Regex::new(r"^[!#$%&'*+.^_`|~0-9A-Za-z-]+$").unwrap();
-        let field_value_re = Regex::new(r"^[ -~]+$").unwrap();
-
-        for (name, value) in headers {
-            let (mut name, mut value) = if parsed {
-                (name.clone(), value.clone())
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[(b"X-City".to_vec(), b"Z\xfcrich".to_vec())], false).unwrap();
+    /// assert_eq!(headers.raw_items(), vec![(b"X-City".to_vec(), b"Z\xfcrich".to_vec())]);
+    ///
+    /// // Control characters are still refused
+    /// assert!(Headers::normalize_and_validate(&[(b"X-City".to_vec(), b"Z\x00rich".to_vec())], false).is_err());
+    ///
+    /// // An empty value is allowed, an empty name isn't
+    /// let mut headers = Headers::normalize_and_validate(&[(b"X-Empty".to_vec(), b"".to_vec())], false).unwrap();
+    /// headers.append(b"Via", b"1.1 proxy").unwrap();
+    /// assert_eq!(headers.get(b"x-empty"), Some(&b""[..]));
+    /// assert!(Headers::normalize_and_validate(&[(b"".to_vec(), b"a".to_vec())], false).is_err());
+    /// ```
+    pub fn normalize_and_validate(headers: &[(Vec<u8>, Vec<u8>)], parsed: bool) -> Result<Headers, ProtocolError> {
+        let items = headers
+            .iter()
+            .map(|(name, value)| (into_payload(name.clone()), into_payload(value.clone())))
+            .collect();
+        Self::normalize(items, parsed)
+    }
+    /// Does the work of `normalize_and_validate` on fields that are already `Payload`s, which
+    /// the parser hands over without copying them.
+    pub(crate) fn normalize(headers: Vec<(Payload, Payload)>, parsed: bool) -> Result<Headers, ProtocolError> {
+        let mut new_headers: Vec<(Payload, Payload, Payload)> = Vec::new();
+        let mut seen_content_length: Option<Payload> = None;
+        let mut saw_chunked = false;
+
+        for (raw_name, mut value) in headers {
+            if !parsed {
+                // A field value may be empty, but a field name can't be
+                check_name_not_empty(&raw_name)?;
+                validate(grammar::is_token, &raw_name, ErrorKind::BadHeader, "Illegal header name")?;
+                validate(grammar::is_field_value, &value, ErrorKind::BadHeader, "Illegal header value")?;
+            }
+
+            // Most names on the wire are title-cased, but ones that are already lower-case can
+            // be shared
+            let name = if raw_name.iter().any(u8::is_ascii_uppercase) {
+                into_payload(raw_name.to_ascii_lowercase())
             } else {
-                let name = normalize_bytes(name)?;
-                let value = normalize_bytes(value)?;
-                validate(&field_name_re, &name, "Illegal header name")?;
-                validate(&field_value_re, &value, "Illegal header value")?;
-                (name, value)
+                raw_name.clone()
             };
 
-            let raw_name = name.clone();
-            name = name.to_ascii_lowercase();
-
-            if name == b"content-length" {
-                let lengths: Vec<Vec<u8>> = value.split(|&b| b == b',')
-                .map(|s| {
-                    // Convert to `&str` before trimming
-                    let s_str = str::from_utf8(s).unwrap_or("");
-                    s_str.trim().as_bytes().to_vec()
-                }) // Change: We need to convert bytes to str first and then trim
-                    .collect();
+            if &name[..] == b"content-length" {
+                let lengths: Vec<&[u8]> = value.split(|&b| b == b',').map(trim_ows).collect();
 
                 if lengths.len() != 1 {
-                    return Err(LocalProtocolError("conflicting Content-Length headers".to_string()));
+                    return Err(ProtocolError::local(
+                        ErrorKind::ConflictingContentLength,
+                        "conflicting Content-Length headers",
+                    ));
                 }
 
-                value = lengths[0].clone();
-                validate(&content_length_re, &value, "bad Content-Length")?;
+                value = share_payload(&value, lengths[0]);
+                validate(grammar::is_content_length, &value, ErrorKind::BadContentLength, "bad Content-Length")?;
 
                 if seen_content_length.is_none() {
                     seen_content_length = Some(value.clone());
                     new_headers.push((raw_name, name, value));
                 } else if seen_content_length.as_ref() != Some(&value) {
-                    return Err(LocalProtocolError("conflicting Content-Length headers".to_string()));
+                    return Err(ProtocolError::local(
+                        ErrorKind::ConflictingContentLength,
+                        "conflicting Content-Length headers",
+                    ));
                 }
-            } else if name == b"transfer-encoding" {
-                if saw_transfer_encoding {
-                    return Err(LocalProtocolError("multiple Transfer-Encoding headers".to_string()));
+            } else if &name[..] == b"transfer-encoding" {
+                // The codings may be split across several fields, but together they form one list
+                value = into_payload(value.to_ascii_lowercase());
+                let codings: Vec<&[u8]> = split_list(&value).collect();
+                if codings.is_empty() {
+                    return Err(ProtocolError::local(ErrorKind::BadHeader, "empty Transfer-Encoding header"));
                 }
-
-                value = value.to_ascii_lowercase();
-                if value != b"chunked" {
-                    return Err(LocalProtocolError("Only Transfer-Encoding: chunked is supported".to_string()));
+                for coding in codings {
+                    let coding_name = trim_ows(coding.split(|&b| b == b';').next().unwrap_or_default());
+                    validate(grammar::is_token, coding_name, ErrorKind::BadHeader, "Illegal transfer coding")?;
+                    if coding == b"chunked" {
+                        if saw_chunked {
+                            return Err(ProtocolError::local(
+                                ErrorKind::BadHeader,
+                                "chunked transfer coding applied more than once",
+                            ));
+                        }
+                        saw_chunked = true;
+                    }
                 }
 
-                saw_transfer_encoding = true;
                 new_headers.push((raw_name, name, value));
             } else {
                 new_headers.push((raw_name, name, value));
             }
         }
 
-        Ok(Headers::new(new_headers))
+        Ok(Headers { full_items: new_headers })
     }
     /// Retrieves comma-separated values from a header.
     ///
@@ -108,17 +410,28 @@ impl Headers {
     ///
     /// # Returns
     /// A vector of values split by commas.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[
+    ///     (b"connection".to_vec(), b"Keep-Alive, Upgrade".to_vec()),
+    ///     (b"CONNECTION".to_vec(), b"close".to_vec()),
+    /// ], false).unwrap();
+    /// let expected = vec![b"keep-alive".to_vec(), b"upgrade".to_vec(), b"close".to_vec()];
+    /// assert_eq!(headers.get_comma_header(b"Connection"), expected);
+    /// assert_eq!(headers.get_comma_header(b"connection"), expected);
+    /// ```
     pub fn get_comma_header(&self, name: &[u8]) -> Vec<Vec<u8>> {
+        let name = name.to_ascii_lowercase();
         let mut out = Vec::new();
 
         for (_, found_name, found_raw_value) in &self.full_items {
-            if found_name == name {
+            if *found_name == name {
                 let parts = found_raw_value.to_ascii_lowercase().split(|&b| b == b',')
-                .map(|s| {
-                    // Convert to `&str` before trimming
-                    let s_str = str::from_utf8(s).unwrap_or("");
-                    s_str.trim().as_bytes().to_vec()
-                })
+                    .map(|s| trim_ows(s).to_vec())
                     .collect::<Vec<Vec<u8>>>();
                 out.extend(parts);
             }
@@ -128,28 +441,71 @@ impl Headers {
     }
     /// Sets new comma-separated values for a header.
     ///
+    /// Every existing field called `name` is dropped and one field is appended per new value,
+    /// so passing no values removes the header. The new fields keep the casing of the first
+    /// field they replace, or are title-cased if there was none.
+    ///
     /// # Arguments
-    /// * `name` - The header name to update.
+    /// * `name` - The lower-case header name to update.
     /// * `new_values` - A vector of new values to be set.
     ///
     /// # Returns
     /// A new `Headers` instance with updated values, or an error if validation fails.
-    pub fn set_comma_header(&self, name: &[u8], new_values: Vec<Vec<u8>>) -> Result<Headers, LocalProtocolError> {
-        let mut new_headers: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> = Vec::new();
-
-        // Remove existing headers with the same name
-        for (raw_name, found_name, found_raw_value) in &self.full_items {
-            if found_name != name {
-                new_headers.push((raw_name.clone(), found_name.clone(), found_raw_value.clone()));
-            }
-        }
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[(b"connection".to_vec(), b"keep-alive".to_vec())], false).unwrap();
+    /// let headers = headers.set_comma_header(b"connection", vec![b"close".to_vec()]).unwrap();
+    /// assert_eq!(headers.raw_items(), vec![(b"connection".to_vec(), b"close".to_vec())]);
+    ///
+    /// let headers = headers.set_comma_header(b"transfer-encoding", vec![b"chunked".to_vec()]).unwrap();
+    /// assert_eq!(headers.get(b"transfer-encoding"), Some(&b"chunked"[..]));
+    /// assert!(headers.iter().any(|(name, _)| name == b"Transfer-Encoding"));
+    /// ```
+    pub fn set_comma_header(&self, name: &[u8], new_values: Vec<Vec<u8>>) -> Result<Headers, ProtocolError> {
+        let raw_name = self
+            .full_items
+            .iter()
+            .find(|(_, lower_name, _)| lower_name.eq_ignore_ascii_case(name))
+            .map_or_else(|| into_payload(title_case(name)), |(raw_name, _, _)| raw_name.clone());
 
-        // Add new comma-separated values
-        for new_value in new_values {
-            new_headers.push((name.to_ascii_uppercase(), name.to_ascii_lowercase(), new_value));
-        }
+        // Remove existing headers with the same name, then add one per new value
+        let mut new_headers = self.clone();
+        new_headers.remove(name);
+        let mut items = new_headers.pairs();
+        items.extend(new_values.into_iter().map(|value| (raw_name.clone(), into_payload(value))));
 
-        Self::normalize_and_validate(&new_headers.iter().map(|(n, v, _)| (n.clone(), v.clone())).collect::<Vec<_>>(), false)
+        Self::normalize(items, false)
+    }
+    /// Returns the transfer codings applied to the body, lower-cased and in the order they were
+    /// applied, gathered from every `Transfer-Encoding` field.
+    ///
+    /// Only `chunked` is handled by the connection itself; any other coding is left on the body
+    /// for the application to undo.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::headers::Headers;
+    ///
+    /// let headers = Headers::normalize_and_validate(&[
+    ///     (b"Transfer-Encoding".to_vec(), b"GZip".to_vec()),
+    ///     (b"Transfer-Encoding".to_vec(), b", chunked".to_vec()),
+    /// ], false).unwrap();
+    /// assert_eq!(headers.transfer_codings(), vec![b"gzip".to_vec(), b"chunked".to_vec()]);
+    ///
+    /// // chunked can only be applied once, and the list can't be empty
+    /// let twice = [(b"Transfer-Encoding".to_vec(), b"chunked, gzip, chunked".to_vec())];
+    /// assert!(Headers::normalize_and_validate(&twice, false).is_err());
+    /// assert!(Headers::normalize_and_validate(&[(b"Transfer-Encoding".to_vec(), b",".to_vec())], false).is_err());
+    /// ```
+    pub fn transfer_codings(&self) -> Vec<Vec<u8>> {
+        let mut codings = self.get_comma_header(b"transfer-encoding");
+        codings.retain(|coding| !coding.is_empty());
+        codings
     }
     /// Checks if the "Expect: 100-continue" header is present.
     ///
@@ -160,39 +516,78 @@ impl Headers {
         expect.iter().any(|value| value == b"100-continue")
     }
 }
-/// Validates the given value against a regular expression.
+/// Iterates over the raw name and value of every header in wire order.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::headers::Headers;
+///
+/// let headers = Headers::normalize_and_validate(&[(b"Host".to_vec(), b"example.com".to_vec())], false).unwrap();
+/// for (name, value) in &headers {
+///     assert_eq!((name, value), (&b"Host"[..], &b"example.com"[..]));
+/// }
+/// ```
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a [u8], &'a [u8]);
+    type IntoIter = Box<dyn Iterator<Item = (&'a [u8], &'a [u8])> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+/// Capitalizes the first letter of every dash-separated word, e.g. `transfer-encoding` becomes
+/// `Transfer-Encoding`.
+fn title_case(name: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(name.len());
+    let mut start_of_word = true;
+    for &b in name {
+        out.push(if start_of_word { b.to_ascii_uppercase() } else { b.to_ascii_lowercase() });
+        start_of_word = b == b'-';
+    }
+    out
+}
+/// Validates the given value against one of the grammar's checks.
 ///
 /// # Arguments
-/// * `re` - A regex pattern to match the value against.
+/// * `matches` - The grammar check the value has to pass.
 /// * `value` - The byte slice to validate.
+/// * `kind` - The error kind to report if validation fails.
 /// * `error_msg` - The error message to return if validation fails.
 ///
 /// # Returns
 /// `Ok(())` if validation succeeds, or an error with a message if it fails.
-fn validate(re: &Regex, value: &[u8], error_msg: &str) -> Result<(), LocalProtocolError> {
-    // Convert value from &[u8] to &str for regex matching
-    if let Ok(value_str) = str::from_utf8(value) {
-        if !re.is_match(value_str) {
-            return Err(LocalProtocolError(format!("{}: {:?}", error_msg, value)));
-        }
-    } else {
-        return Err(LocalProtocolError(format!("Invalid UTF-8 value: {:?}", value)));
+fn validate(matches: fn(&[u8]) -> bool, value: &[u8], kind: ErrorKind, error_msg: &str) -> Result<(), ProtocolError> {
+    if !matches(value) {
+        return Err(ProtocolError::local(kind, format!("{}: {:?}", error_msg, value)));
     }
 
     Ok(())
 }
-/// Normalizes the input bytes to a `Vec<u8>`.
+/// Splits a comma-separated list into its elements, skipping the empty ones the list syntax
+/// allows.
+fn split_list(value: &[u8]) -> impl Iterator<Item = &[u8]> {
+    value.split(|&b| b == b',').map(trim_ows).filter(|element| !element.is_empty())
+}
+/// Strips optional whitespace (spaces and tabs) from both ends of a list element.
+pub(crate) fn trim_ows(value: &[u8]) -> &[u8] {
+    let is_ows = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = value.iter().position(|b| !is_ows(b)).unwrap_or(value.len());
+    let end = value.iter().rposition(|b| !is_ows(b)).map_or(start, |i| i + 1);
+    &value[start..end]
+}
+/// Checks that a header name isn't empty.
 ///
 /// # Arguments
-/// * `input` - The byte slice to normalize.
+/// * `name` - The header name to check.
 ///
 /// # Returns
-/// `Ok(Vec<u8>)` if successful, or an error if the input is empty.
-fn normalize_bytes(input: &[u8]) -> Result<Vec<u8>, LocalProtocolError> {
-    if input.is_empty() {
-        return Err(LocalProtocolError("Empty input".to_string()));
+/// `Ok(())` if successful, or an error if the name is empty.
+fn check_name_not_empty(name: &[u8]) -> Result<(), ProtocolError> {
+    if name.is_empty() {
+        return Err(ProtocolError::local(ErrorKind::BadHeader, "Empty header name"));
     }
 
-    Ok(input.to_vec())
+    Ok(())
 }
 
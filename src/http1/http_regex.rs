@@ -1,4 +1,7 @@
-use regex::Regex;
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+
+use super::grammar::{HeaderField, RequestLine, StatusLine};
 
 /// Optional Whitespace (OWS) – RFC 7230 Section 3.2.3
 /// Represents zero or more spaces or tabs.
@@ -15,13 +18,16 @@ pub const FIELD_NAME: &str = TOKEN;
 /// Visible characters (vchar) – RFC 7230 Section 3.2.6
 /// Represents visible ASCII characters (excluding control characters).
 pub const VCHAR: &str = r"[\x21-\x7e]";
+/// Visible characters or obs-text (0x80-0xFF) – RFC 7230 Section 3.2.6
+/// Every pattern is compiled with Unicode off, so this matches single raw bytes rather than
+/// UTF-8 sequences, and a Latin-1 header value is as acceptable as an ASCII one.
 pub const VCHAR_OR_OBS_TEXT: &str = r"[^\x00\s]";
 pub const FIELD_VCHAR: &str = VCHAR_OR_OBS_TEXT;
 
 /// Field content – RFC 7230 Section 3.2.6
 /// Represents the content of a field (may include multiple vchar-separated segments).
 pub fn field_content() -> String {
-    format!(r"{}+(?:[ \t]+{})*", FIELD_VCHAR, FIELD_VCHAR)
+    format!(r"{}+(?:[ \t]+{}+)*", FIELD_VCHAR, FIELD_VCHAR)
 }
 
 /// Field value – RFC 7230 Section 3.2.6
@@ -34,11 +40,10 @@ pub fn field_value() -> String {
 /// Represents a complete header field (name + optional value with OWS).
 pub fn header_field() -> String {
     format!(
-        r"(?P<field_name>{}){}{}(?P<field_value>{}){}",
-        FIELD_NAME, 
-        OWS, 
-        ":", 
-        field_value(), 
+        r"(?P<field_name>{}):{}(?P<field_value>{}){}",
+        FIELD_NAME,
+        OWS,
+        field_value(),
         OWS
     )
 }
@@ -76,30 +81,36 @@ pub const REASON_PHRASE: &str = r"([ \t]|[^\x00\s])*";
 /// Represents the status line of an HTTP response (version, status code, reason).
 pub fn status_line() -> String {
     format!(
-        r"{} (?P<status_code>{}) (?: (?P<reason>{}))?",
+        r"{} (?P<status_code>{})(?: (?P<reason>{}))?",
         HTTP_VERSION, STATUS_CODE, REASON_PHRASE
     )
 }
 
 /// Chunk size – RFC 7230 Section 3.1.2.2
-/// Represents the chunk size in hexadecimal format (1 to 20 hex digits).
+/// Represents the chunk size in hexadecimal format (1 to 16 hex digits, so it fits in 64 bits).
 pub const HEXDIG: &str = r"[0-9A-Fa-f]";
-pub const CHUNK_SIZE: &str = r"({HEXDIG}){{1,20}}";
+pub const CHUNK_SIZE: &str = r"[0-9A-Fa-f]{1,16}";
 
-/// Chunk extension – RFC 7230 Section 3.1.2.2
-/// Represents optional extensions for chunks.
-pub const CHUNK_EXT: &str = r";.*";
+/// Chunk extension – RFC 9112 Section 7.1.1
+/// Represents optional extensions for chunks. Like a field value, an extension can't hold CR,
+/// LF or other control whitespace, so the line can't be taken to end anywhere else. Spaces and
+/// tabs are allowed anywhere in it, which covers the BWS around names, `=` and values.
+pub const CHUNK_EXT: &str = r";(?:[ \t]|[^\x00\s])*";
 
-/// Chunk header – RFC 7230 Section 3.1.2.2
-/// Represents the full chunk header (size + optional extension).
+/// Chunk header – RFC 9112 Section 7.1.1
+/// Represents the full chunk header (size + optional extension, which may follow BWS), including
+/// its CRLF. Unlike head lines, a chunk-size line can't end in a bare LF.
 pub fn chunk_header() -> String {
     format!(
-        r"(?P<chunk_size>{}) (?P<chunk_ext>{})?{}\\r\\n",
-        CHUNK_SIZE, CHUNK_EXT, OWS
+        r"(?P<chunk_size>{})(?:{}(?P<chunk_ext>{}))?{}\r\n",
+        CHUNK_SIZE, OWS, CHUNK_EXT, OWS
     )
 }
 /// Compiles a regex pattern and returns a `Regex` instance.
 ///
+/// The pattern is matched against raw bytes with Unicode mode off, so a class like `[^\s]`
+/// matches any single byte outside it, including 0x80-0xFF.
+///
 /// # Arguments
 /// * `pattern` - The regex pattern to compile.
 ///
@@ -109,7 +120,89 @@ pub fn chunk_header() -> String {
 /// # Panics
 /// If the pattern is invalid.
 pub fn compile_regex(pattern: &str) -> Regex {
-    Regex::new(pattern).expect("Invalid regex pattern")
+    Regex::new(&format!("(?-u){}", pattern)).expect("Invalid regex pattern")
+}
+
+/// Compiles `pattern` so that it only matches an entire input, never a piece of one.
+fn compile_anchored(pattern: &str) -> Regex {
+    compile_regex(&format!("^(?:{})$", pattern))
+}
+
+lazy_static! {
+    /// An HTTP method on its own.
+    pub static ref METHOD_RE: Regex = compile_anchored(METHOD);
+    /// A request target on its own.
+    pub static ref REQUEST_TARGET_RE: Regex = compile_anchored(REQUEST_TARGET);
+    /// A header field name on its own.
+    pub static ref FIELD_NAME_RE: Regex = compile_anchored(FIELD_NAME);
+    /// A header field value on its own, without surrounding whitespace.
+    pub static ref FIELD_VALUE_RE: Regex = compile_anchored(&field_value());
+    /// A `Content-Length` value.
+    pub static ref CONTENT_LENGTH_RE: Regex = compile_anchored("[0-9]+");
+    /// A whole request line, without its CRLF.
+    pub static ref REQUEST_LINE_RE: Regex = compile_anchored(&request_line());
+    /// A whole status line, without its CRLF.
+    pub static ref STATUS_LINE_RE: Regex = compile_anchored(&status_line());
+    /// A whole header line, without its CRLF.
+    pub static ref HEADER_FIELD_RE: Regex = compile_anchored(&header_field());
+    /// A whole chunk-size line, CRLF included.
+    pub static ref CHUNK_HEADER_RE: Regex = compile_anchored(&chunk_header());
+}
+
+/// Parses a request line with `REQUEST_LINE_RE`.
+pub fn parse_request_line(line: &[u8]) -> Option<RequestLine<'_>> {
+    let captures = REQUEST_LINE_RE.captures(line)?;
+    Some(RequestLine {
+        method: captures.name("method")?.as_bytes(),
+        target: captures.name("target")?.as_bytes(),
+        http_version: captures.name("http_version")?.as_bytes(),
+    })
+}
+
+/// Parses a status line with `STATUS_LINE_RE`.
+pub fn parse_status_line(line: &[u8]) -> Option<StatusLine<'_>> {
+    let captures = STATUS_LINE_RE.captures(line)?;
+    // The grammar allows exactly three digits, which always fit
+    let status_code = captures.name("status_code")?.as_bytes()
+        .iter()
+        .fold(0u16, |code, digit| code * 10 + u16::from(digit - b'0'));
+    Some(StatusLine {
+        http_version: captures.name("http_version")?.as_bytes(),
+        status_code,
+        reason: captures.name("reason").map_or(&[][..], |reason| reason.as_bytes()),
+    })
 }
 
+/// Parses a header line with `HEADER_FIELD_RE`.
+pub fn parse_header_field(line: &[u8]) -> Option<HeaderField<'_>> {
+    let captures = HEADER_FIELD_RE.captures(line)?;
+    Some(HeaderField {
+        name: captures.name("field_name")?.as_bytes(),
+        value: captures.name("field_value").map_or(&[][..], |value| value.as_bytes()),
+    })
+}
+
+/// Parses a chunk-size line with `CHUNK_HEADER_RE` and returns its hex digits.
+pub fn parse_chunk_header(line: &[u8]) -> Option<&[u8]> {
+    Some(CHUNK_HEADER_RE.captures(line)?.name("chunk_size")?.as_bytes())
+}
+
+/// Checks a method or header name with `METHOD_RE`, which is the `token` grammar.
+pub fn is_token(bytes: &[u8]) -> bool {
+    METHOD_RE.is_match(bytes)
+}
 
+/// Checks a request target with `REQUEST_TARGET_RE`.
+pub fn is_request_target(bytes: &[u8]) -> bool {
+    REQUEST_TARGET_RE.is_match(bytes)
+}
+
+/// Checks a header value with `FIELD_VALUE_RE`.
+pub fn is_field_value(bytes: &[u8]) -> bool {
+    FIELD_VALUE_RE.is_match(bytes)
+}
+
+/// Checks a `Content-Length` value with `CONTENT_LENGTH_RE`.
+pub fn is_content_length(bytes: &[u8]) -> bool {
+    CONTENT_LENGTH_RE.is_match(bytes)
+}
@@ -1,255 +1,289 @@
 use std::collections::HashMap;
 
-/// ReceiveBuffer struct is used to recieve the data into a buffer, in byte form.
-#[derive(Debug)]
-pub struct ReceiveBuffer {
-    data: Vec<u8>,
-}
+use lazy_static::lazy_static;
 
-impl ReceiveBuffer {
-    /// to extract data from the buffer
-    /// ```
-    /// let mut buffer = ReceiveBuffer { data: vec![72, 101, 108, 108, 111] };
-    /// let lines = buffer.maybe_extract_lines();
-    /// assert_eq!(lines, Some(vec![vec![72, 101, 108, 108, 111]]));
-    /// ```
-    pub fn maybe_extract_lines(&mut self) -> Option<Vec<Vec<u8>>> {
-        
-        Some(vec![self.data.clone()])
+use super::events::{
+    into_payload, share_payload, ConnectionClosed, Data, EndOfMessage, Event, Headers, InformationalResponse, Payload,
+    Request, Response,
+};
+use super::framing::Framing;
+use super::grammar;
+use super::recieve_buffer::ReceiveBuffer;
+use super::state::Sentinel;
+use super::util::{ErrorKind, ProtocolError};
+
+/// Reader implementation from the reader.rs file
+///
+/// `read` pulls at most one event out of the buffer, or `Ok(None)` if more data is needed.
+/// `read_eof` is called once the peer has closed the connection and the buffer is empty; the
+/// default is to report a clean close, which is right for every reader that isn't in the middle
+/// of a message body.
+/// `oversized_error` is what the connection reports when the buffer outgrows its limit while
+/// `read` still wants more data; by default that means a head too big to accept.
+/// `set_allow_obs_fold` changes whether folded header lines are accepted from now on, even
+/// partway through a message; readers that never see header lines ignore it.
+pub trait Reader {
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError>;
+    fn set_allow_obs_fold(&mut self, _allow_obs_fold: bool) {}
+    fn read_eof(&mut self) -> Result<Event, ProtocolError> {
+        Ok(Event::ConnectionClosed(ConnectionClosed))
     }
-     /// Tries to extract at most `length` bytes from the buffer.
-    /// If the buffer has enough data, it drains the first `length` bytes and returns them.
-    /// If not, it returns `None`.
-    ///
-    /// # Arguments
-    /// * `length` - The maximum number of bytes to extract from the buffer.
-    ///
-    /// # Returns
-    /// Returns an `Option<Vec<u8>>` containing the extracted bytes if available, or `None` if there aren't enough bytes.
-    ///
-    /// # Example
-    /// ```
-    /// let mut buffer = ReceiveBuffer { data: vec![1, 2, 3, 4, 5] };
-    /// let extracted = buffer.maybe_extract_at_most(3);
-    /// assert_eq!(extracted, Some(vec![1, 2, 3]));
-    /// ```
-    pub fn maybe_extract_at_most(&mut self, length: usize) -> Option<Vec<u8>> {
-        if self.data.len() >= length {
-            Some(self.data.drain(0..length).collect())
-        } else {
-            None
+    fn oversized_error(&self) -> ProtocolError {
+        ProtocolError::new_remote(ErrorKind::OversizedHead, "Receive buffer too long", 431)
+    }
+}
+/// Reports a line that doesn't match its grammar as the peer's fault.
+fn bad_line(kind: ErrorKind, msg: &str, line: &[u8]) -> ProtocolError {
+    ProtocolError::remote(kind, format!("{} {:?}", msg, String::from_utf8_lossy(line)))
+}
+/// Joins obs-fold continuation lines (ones starting with SP or HTAB) onto the line before them.
+///
+/// RFC 9112 section 5.2 lets a recipient replace each fold with a single space; unless
+/// `allow_obs_fold` is set we refuse folded headers instead, since a sender still using them
+/// is as likely to be confused about framing as anything else.
+fn unfold_header_lines(lines: &[Payload], allow_obs_fold: bool) -> Result<Vec<Payload>, ProtocolError> {
+    let mut unfolded: Vec<Payload> = Vec::with_capacity(lines.len());
+    for line in lines {
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            unfolded.push(line.clone());
+            continue;
         }
+        let previous = match unfolded.last_mut() {
+            // A folded framing header is read one way by us and another by anything that
+            // doesn't unfold, which is all a smuggled request needs
+            Some(previous) if allow_obs_fold && is_framing_header_line(previous) => {
+                return Err(ProtocolError::remote(
+                    ErrorKind::AmbiguousFraming,
+                    "obsolete line folding is not allowed in Content-Length or Transfer-Encoding",
+                ))
+            }
+            Some(previous) if allow_obs_fold => previous,
+            Some(_) => {
+                return Err(ProtocolError::remote(
+                    ErrorKind::BadHeader,
+                    "obsolete line folding is not allowed in headers",
+                ))
+            }
+            None => {
+                return Err(ProtocolError::remote(
+                    ErrorKind::BadHeader,
+                    "continuation line at start of headers",
+                ))
+            }
+        };
+        // Only a folded line is copied, since it no longer matches anything in the buffer
+        let end = previous.iter().rposition(|&b| b != b' ' && b != b'\t').map_or(0, |i| i + 1);
+        let mut joined = previous[..end].to_vec();
+        joined.push(b' ');
+        let start = line.iter().position(|&b| b != b' ' && b != b'\t').unwrap_or(line.len());
+        joined.extend_from_slice(&line[start..]);
+        *previous = into_payload(joined);
     }
-     /// Checks if the next line in the buffer is obviously an invalid request line.
-    ///
-    /// # Returns
-    /// Always returns `false` in this placeholder implementation.
-    ///
-    /// # Example
-    /// ```
-    /// let buffer = ReceiveBuffer { data: vec![72, 101, 108, 108, 111] };
-    /// assert_eq!(buffer.is_next_line_obviously_invalid_request_line(), false);
-    /// ```
-    pub fn is_next_line_obviously_invalid_request_line(&self) -> bool {
-        false
+    Ok(unfolded)
+}
+/// Checks whether a header line sets `Content-Length` or `Transfer-Encoding`.
+fn is_framing_header_line(line: &[u8]) -> bool {
+    let name = line.split(|&b| b == b':').next().unwrap_or_default();
+    name.eq_ignore_ascii_case(b"content-length") || name.eq_ignore_ascii_case(b"transfer-encoding")
+}
+/// Decodes the header lines of a head into normalized `Headers`, keeping wire order.
+///
+/// Names and values are taken from the lines they were parsed out of, so with the `bytes`
+/// feature they share the receive buffer's allocation.
+fn decode_header_lines(lines: &[Payload], allow_obs_fold: bool) -> Result<Headers, ProtocolError> {
+    let lines = unfold_header_lines(lines, allow_obs_fold)?;
+    let mut pairs = Vec::with_capacity(lines.len());
+    for line in &lines {
+        let field = grammar::parse_header_field(line)
+            .ok_or_else(|| bad_line(ErrorKind::BadHeader, "illegal header line:", line))?;
+        pairs.push((share_payload(line, field.name), share_payload(line, field.value)));
     }
-    /// Attempts to extract the next line from the buffer. 
-    /// It calls `maybe_extract_at_most` to extract all available bytes, treating them as one line.
-    ///
-    /// # Returns
-    /// Returns an `Option<Vec<u8>>` containing the extracted line or `None` if there are no bytes left.
-    ///
-    /// # Example
-    /// ```
-    /// let mut buffer = ReceiveBuffer { data: vec![72, 101, 108, 108, 111] };
-    /// let line = buffer.maybe_extract_next_line();
-    /// assert_eq!(line, Some(vec![72, 101, 108, 108, 111]));
-    /// ```
-    pub fn maybe_extract_next_line(&mut self) -> Option<Vec<u8>> {
-        self.maybe_extract_at_most(self.data.len())
+    Headers::normalize(pairs, true).map_err(ProtocolError::into_remote)
+}
+/// Fields a trailer must not carry, RFC 9110 section 6.5.1: ones that frame the message or route
+/// it, which a recipient merging the trailer into the head would act on too late or not at all.
+const FORBIDDEN_TRAILER_FIELDS: &[&[u8]] = &[b"content-length", b"transfer-encoding", b"host"];
+/// Refuses a trailer block that sets a framing or routing field.
+fn check_trailer_fields(trailers: &Headers) -> Result<(), ProtocolError> {
+    match FORBIDDEN_TRAILER_FIELDS.iter().find(|name| trailers.contains(name)) {
+        Some(name) => Err(ProtocolError::remote(
+            ErrorKind::BadHeader,
+            format!("{} is not allowed in a trailer", String::from_utf8_lossy(name)),
+        )),
+        None => Ok(()),
     }
 }
-/// Implements the `Display` trait for `LocalProtocolError`.
-/// This allows for more human-readable error messages when using `println!` or `format!`.
-/// The `Display` trait formats the error as: `LocalProtocolError: <error_message>`.
+/// Reads a request head from a client that is in the `Idle` state.
+///
+/// Folded header lines are rejected unless the reader was built with `with_obs_fold(true)`.
 ///
 /// # Example
-/// 
-/// Here's an example of how to use the `Display` trait to print the error:
-/// 
+///
 /// ```rust
-/// // Creating a LocalProtocolError with a custom error message.
-/// let error = LocalProtocolError("Failed to parse local protocol message.".to_string());
-/// 
-/// // Printing the error using Display trait.
-/// println!("{}", error);  // Output: LocalProtocolError: Failed to parse local protocol message.
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{Reader, RequestHeadReader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+/// let mut reader = RequestHeadReader::default();
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::Request(request)) => assert_eq!(request.target, b"/"),
+///     other => panic!("unexpected {:?}", other),
+/// }
+///
+/// // Whitespace before the colon of a header is never allowed
+/// buffer.append(b"GET / HTTP/1.1\r\nHost : example.com\r\n\r\n");
+/// assert!(reader.read(&mut buffer).is_err());
+///
+/// // Neither is obs-fold, unless asked for
+/// let folded = b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Note: one\r\n\t two\r\n\r\n";
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(folded);
+/// let error = RequestHeadReader::default().read(&mut buffer).unwrap_err();
+/// assert_eq!(error.error_status_hint(), 400);
+///
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(folded);
+/// match RequestHeadReader::default().with_obs_fold(true).read(&mut buffer).unwrap() {
+///     Some(Event::Request(request)) => {
+///         assert_eq!(request.headers.get_comma_header(b"x-note"), vec![b"one two".to_vec()]);
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// }
 /// ```
-/// 
-/// The `Display` trait allows the error to be formatted in a more user-friendly way.
-#[derive(Debug)]
-pub struct LocalProtocolError(pub String);
+#[derive(Default)]
+pub struct RequestHeadReader {
+    allow_obs_fold: bool,
+}
 
-impl std::fmt::Display for LocalProtocolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "LocalProtocolError: {}", self.0)
+impl RequestHeadReader {
+    /// Unfolds obs-fold continuation lines into a single space instead of rejecting them.
+    pub fn with_obs_fold(mut self, allow_obs_fold: bool) -> Self {
+        self.allow_obs_fold = allow_obs_fold;
+        self
     }
 }
-/// A struct representing an error specific to a remote protocol issue.
-/// It holds a `String` that describes the error message.
-///
-/// # Example
-/// 
-/// Here's an example of how to use the `RemoteProtocolError` struct:
-/// 
-/// ```rust
-/// // Creating a RemoteProtocolError with a custom error message.
-/// let error = RemoteProtocolError("Connection lost while fetching remote data.".to_string());
-/// 
-/// // Printing the error using Debug trait.
-/// println!("{:?}", error);  // Output: RemoteProtocolError("Connection lost while fetching remote data.")
-/// ```
-/// 
-/// This struct is used to encapsulate error messages related to remote protocol issues.
-/// The `Debug` trait is derived automatically, which allows the error to be printed using the `{:?}` format.
-#[derive(Debug)]
-pub struct RemoteProtocolError(pub String);
 
-impl std::fmt::Display for RemoteProtocolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "RemoteProtocolError: {}", self.0)
+impl Reader for RequestHeadReader {
+    fn set_allow_obs_fold(&mut self, allow_obs_fold: bool) {
+        self.allow_obs_fold = allow_obs_fold;
+    }
+
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
+        let lines = match buf.maybe_extract_lines() {
+            Some(lines) => lines,
+            None => {
+                if buf.is_next_line_obviously_invalid_request_line() {
+                    return Err(ProtocolError::remote(ErrorKind::BadRequestLine, "illegal request line"));
+                }
+                return Ok(None);
+            }
+        };
+        let (request_line, header_lines) = lines
+            .split_first()
+            .ok_or_else(|| ProtocolError::remote(ErrorKind::BadRequestLine, "no request line received"))?;
+        let matches = grammar::parse_request_line(request_line)
+            .ok_or_else(|| bad_line(ErrorKind::BadRequestLine, "illegal request line:", request_line))?;
+        let request = Request::with_headers(
+            matches.method.to_vec(),
+            matches.target.to_vec(),
+            decode_header_lines(header_lines, self.allow_obs_fold)?,
+            matches.http_version.to_vec(),
+        )
+        .map_err(ProtocolError::into_remote)?;
+        Ok(Some(Event::Request(request)))
     }
 }
-/// Represents an HTTP request, including headers and parsed status.
-/// 
-/// # Fields:
-/// - `headers`: A vector of header key-value pairs (`Vec<u8>`, `Vec<u8>`).
-/// - `parsed`: A boolean indicating if the request has been parsed.
+/// Reads a response head (informational or final) from a server that owes us a response.
 ///
-/// # Example
-/// 
-/// ```rust
-/// let request = Request {
-///     headers: vec![(b"Content-Type".to_vec(), b"application/json".to_vec())],
-///     parsed: true,
-/// };
-/// println!("{}", request.parsed);  // Output: true
-/// ```
-pub struct Request {
-    headers: Vec<(Vec<u8>, Vec<u8>)>,
-    parsed: bool,
-}
-/// Represents an HTTP response, including status code, reason, headers, and parsed status.
-/// 
-/// # Fields:
-/// - `status_code`: The HTTP status code.
-/// - `reason`: The reason phrase associated with the status code.
-/// - `http_version`: The HTTP version.
-/// - `headers`: A vector of header key-value pairs (`Vec<u8>`, `Vec<u8>`).
-/// - `parsed`: A boolean indicating if the response has been parsed.
+/// Folded header lines are rejected unless the reader was built with `with_obs_fold(true)`.
 ///
 /// # Example
-/// 
-/// ```rust
-/// let response = Response {
-///     status_code: 200,
-///     reason: b"OK".to_vec(),
-///     http_version: b"HTTP/1.1".to_vec(),
-///     headers: vec![(b"Content-Type".to_vec(), b"text/html".to_vec())],
-///     parsed: true,
-/// };
-/// println!("{}", response.status_code);  // Output: 200
-/// ```
-pub struct Response {
-    status_code: u16,
-    reason: Vec<u8>,
-    http_version: Vec<u8>,
-    headers: Vec<(Vec<u8>, Vec<u8>)>,
-    parsed: bool,
-}
-/// Represents an informational HTTP response (status codes 1xx), including status code, reason, headers, and parsed status.
-/// 
-/// # Fields:
-/// - `status_code`: The HTTP status code (usually in the 1xx range).
-/// - `reason`: The reason phrase associated with the status code.
-/// - `http_version`: The HTTP version.
-/// - `headers`: A vector of header key-value pairs (`Vec<u8>`, `Vec<u8>`).
-/// - `parsed`: A boolean indicating if the response has been parsed.
 ///
-/// # Example
-/// 
 /// ```rust
-/// let info_response = InformationalResponse {
-///     status_code: 100,
-///     reason: b"Continue".to_vec(),
-///     http_version: b"HTTP/1.1".to_vec(),
-///     headers: vec![(b"Content-Length".to_vec(), b"0".to_vec())],
-///     parsed: true,
-/// };
-/// println!("{}", info_response.status_code);  // Output: 100
-/// ```
-pub struct InformationalResponse {
-    status_code: u16,
-    reason: Vec<u8>,
-    http_version: Vec<u8>,
-    headers: Vec<(Vec<u8>, Vec<u8>)>,
-    parsed: bool,
-}
-/// Represents chunked data in an HTTP message, including the data and chunk boundaries.
-/// 
-/// # Fields:
-/// - `data`: A vector of bytes representing the data chunk.
-/// - `chunk_start`: A boolean indicating if this chunk is the start of a chunked message.
-/// - `chunk_end`: A boolean indicating if this chunk is the end of a chunked message.
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{Reader, ResponseHeadReader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
 ///
-/// # Example
-/// 
-/// ```rust
-/// let data = Data {
-///     data: b"Hello".to_vec(),
-///     chunk_start: true,
-///     chunk_end: false,
-/// };
-/// println!("{}", data.chunk_start);  // Output: true
-/// ```
-#[derive(Debug)]
-pub struct Data {
-    data: Vec<u8>,
-    chunk_start: bool,
-    chunk_end: bool,
-}
-/// Represents the end of an HTTP message, including headers.
-/// 
-/// # Fields:
-/// - `headers`: A vector of header key-value pairs (`Vec<u8>`, `Vec<u8>`).
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+/// let mut reader = ResponseHeadReader::default();
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::Response(response)) => assert_eq!(response.status_code, 200),
+///     other => panic!("unexpected {:?}", other),
+/// }
 ///
-/// # Example
-/// 
-/// ```rust
-/// let end_of_message = EndOfMessage {
-///     headers: vec![(b"Content-Type".to_vec(), b"application/json".to_vec())],
-/// };
-/// println!("{:?}", end_of_message.headers);  // Output: [("Content-Type", "application/json")]
+/// buffer.append(b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>\r\n\r\n");
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::InformationalResponse(response)) => assert_eq!(response.reason, b"Early Hints"),
+///     other => panic!("unexpected {:?}", other),
+/// }
+///
+/// // Header values and reason phrases are bytes, and obs-text such as Latin-1 is fine
+/// buffer.append(b"HTTP/1.1 200 Tr\xe8s bien\r\nX-Server: Caf\xe9\r\n\r\n");
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::Response(response)) => {
+///         assert_eq!(response.reason, b"Tr\xe8s bien");
+///         assert_eq!(response.headers.get_comma_header(b"x-server"), vec![b"caf\xe9".to_vec()]);
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// }
 /// ```
-#[derive(Debug)]
-pub struct EndOfMessage {
-    headers: Vec<(Vec<u8>, Vec<u8>)>,
+#[derive(Default)]
+pub struct ResponseHeadReader {
+    allow_obs_fold: bool,
 }
-/// Reader implementation from the reader.rs file
-pub trait Reader {
-    fn read(&mut self, buf: &mut ReceiveBuffer) -> Option<Data>;
-    fn read_eof(&mut self) -> Result<EndOfMessage, RemoteProtocolError>;
+
+impl ResponseHeadReader {
+    /// Unfolds obs-fold continuation lines into a single space instead of rejecting them.
+    pub fn with_obs_fold(mut self, allow_obs_fold: bool) -> Self {
+        self.allow_obs_fold = allow_obs_fold;
+        self
+    }
+}
+
+impl Reader for ResponseHeadReader {
+    fn set_allow_obs_fold(&mut self, allow_obs_fold: bool) {
+        self.allow_obs_fold = allow_obs_fold;
+    }
+
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
+        let lines = match buf.maybe_extract_lines() {
+            Some(lines) => lines,
+            None => return Ok(None),
+        };
+        let (status_line, header_lines) = lines
+            .split_first()
+            .ok_or_else(|| ProtocolError::remote(ErrorKind::BadStatusLine, "no response line received"))?;
+        let matches = grammar::parse_status_line(status_line)
+            .ok_or_else(|| bad_line(ErrorKind::BadStatusLine, "illegal status line:", status_line))?;
+        let status_code = matches.status_code;
+        let reason = matches.reason.to_vec();
+        let headers = decode_header_lines(header_lines, self.allow_obs_fold)?;
+        let http_version = matches.http_version.to_vec();
+        let event = if status_code < 200 {
+            InformationalResponse::with_headers(status_code, headers, reason, http_version)
+                .map(Event::InformationalResponse)
+        } else {
+            Response::with_headers(status_code, headers, reason, http_version)
+                .map(Event::Response)
+        };
+        event.map(Some).map_err(ProtocolError::into_remote)
+    }
 }
 /// A reader that handles content with a specified length.
-/// 
+///
 /// # Fields:
 /// - `length`: Total content length.
 /// - `remaining`: Remaining content length to be read.
 ///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::readers::ContentLengthReader;
+///
 /// let reader = ContentLengthReader::new(100);
-/// println!("{}", reader.remaining);  // Output: 100
 /// ```
 pub struct ContentLengthReader {
     length: usize,
@@ -258,10 +292,11 @@ pub struct ContentLengthReader {
 /// Creates a new `ContentLengthReader` with a specified total length.
     ///
     /// # Example
-    /// 
+    ///
     /// ```rust
+    /// use yo::http1::readers::ContentLengthReader;
+    ///
     /// let reader = ContentLengthReader::new(200);
-    /// println!("{}", reader.length);  // Output: 200
     /// ```
 impl ContentLengthReader {
     pub fn new(length: usize) -> Self {
@@ -272,247 +307,479 @@ impl ContentLengthReader {
     }
 }
 /// Implements the `Reader` trait for `ContentLengthReader`.
-/// 
+///
 /// # Methods:
-/// - `read`: Reads data up to the remaining content length from the buffer.
-/// - `read_eof`: Checks if the entire content has been read and handles EOF errors.
+/// - `read`: Reads whatever part of the body is buffered, then `EndOfMessage` once it is complete.
+/// - `read_eof`: Errors if the peer closed before the whole body arrived.
 ///
 /// # Example
-/// 
+///
 /// ```rust
-/// let mut reader = ContentLengthReader::new(100);
-/// let mut buffer = ReceiveBuffer { data: vec![1, 2, 3] };
-/// 
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{ContentLengthReader, Reader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut reader = ContentLengthReader::new(5);
+/// let mut buffer = ReceiveBuffer::new();
+/// buffer.append(b"Hel");
+///
 /// // Reading data from the buffer.
-/// if let Some(data) = reader.read(&mut buffer) {
-///     println!("{:?}", data);
-/// }
-/// 
-/// // Checking if the content is fully read.
-/// match reader.read_eof() {
-///     Ok(end_of_message) => println!("End of message: {:?}", end_of_message),
-///     Err(e) => println!("Error: {}", e),
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::Data(data)) => assert_eq!(&data.data[..], b"Hel"),
+///     other => panic!("unexpected {:?}", other),
 /// }
+///
+/// // The peer hung up before sending the last two bytes.
+/// assert!(reader.read_eof().is_err());
 /// ```
 impl Reader for ContentLengthReader {
-    fn read(&mut self, buf: &mut ReceiveBuffer) -> Option<Data> {
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
         if self.remaining == 0 {
-            return Some(Data {
-                data: vec![],
-                chunk_start: false,
-                chunk_end: false,
-            });
+            return Ok(Some(Event::EndOfMessage(EndOfMessage::default())));
         }
-        let data = buf.maybe_extract_at_most(self.remaining);
-        match data {
-            Some(data) => {
-                self.remaining -= data.len();
-                Some(Data {
-                    data,
-                    chunk_start: false,
-                    chunk_end: self.remaining == 0,
-                })
-            }
-            None => None,
+        let available = buf.len().min(self.remaining);
+        if available == 0 {
+            return Ok(None);
         }
+        let data = buf.maybe_extract_at_most(available).unwrap_or_default();
+        self.remaining -= data.len();
+        Ok(Some(Event::Data(Data::new(data, false, false))))
     }
 
-    fn read_eof(&mut self) -> Result<EndOfMessage, RemoteProtocolError> {
+    fn read_eof(&mut self) -> Result<Event, ProtocolError> {
         if self.remaining > 0 {
-            Err(RemoteProtocolError(format!(
-                "peer closed connection without sending complete message body (received {} bytes, expected {})",
-                self.length - self.remaining,
-                self.length
-            )))
+            Err(ProtocolError::remote(
+                ErrorKind::IncompleteMessage,
+                format!(
+                    "peer closed connection without sending complete message body (received {} bytes, expected {})",
+                    self.length - self.remaining,
+                    self.length
+                ),
+            ))
         } else {
-            Ok(EndOfMessage {
-                headers: vec![], // Simplified for illustration
-            })
+            Ok(Event::EndOfMessage(EndOfMessage::default()))
         }
     }
 }
 /// A reader for chunked transfer encoding in HTTP.
-/// 
+///
 /// # Fields:
-/// - `bytes_in_chunk`: The number of bytes in the current chunk.
-/// - `bytes_to_discard`: Bytes to discard (e.g., for chunk trailers).
+/// - `bytes_in_chunk`: The number of bytes of the current chunk still to be read.
+/// - `bytes_to_discard`: How much of the CRLF that ends each chunk's data is still to be read.
+/// - `pending_chunk_start`: Whether the next `Data` is the first of its chunk, which can only
+///   be known when the size line is read.
 /// - `reading_trailer`: Flag indicating if the trailer is being read.
 ///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::readers::ChunkedReader;
+///
 /// let reader = ChunkedReader::new();
-/// println!("{}", reader.bytes_in_chunk);  // Output: 0
 /// ```
+#[derive(Default)]
 pub struct ChunkedReader {
     bytes_in_chunk: usize,
     bytes_to_discard: usize,
+    pending_chunk_start: bool,
     reading_trailer: bool,
+    allow_obs_fold: bool,
 }
 /// Creates a new `ChunkedReader` with initial values.
 ///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::readers::ChunkedReader;
+///
 /// let reader = ChunkedReader::new();
-/// println!("{}", reader.reading_trailer);  // Output: false
 /// ```
 impl ChunkedReader {
     pub fn new() -> Self {
         ChunkedReader {
             bytes_in_chunk: 0,
             bytes_to_discard: 0,
+            pending_chunk_start: false,
             reading_trailer: false,
+            allow_obs_fold: false,
         }
     }
+    /// Lets the trailer block use obs-fold, the same as the head reader it follows.
+    pub fn with_obs_fold(mut self, allow_obs_fold: bool) -> Self {
+        self.allow_obs_fold = allow_obs_fold;
+        self
+    }
 }
 /// Implements the `Reader` trait for `ChunkedReader`.
-/// 
+///
 /// # Methods:
-/// - `read`: Reads chunked data from the buffer, processing the chunk and trailer phases.
-/// - `read_eof`: Handles EOF by returning an error if the body is incomplete.
+/// - `read`: Reads chunked data from the buffer, one chunk-size line, piece of chunk data or
+///   trailer block at a time. Chunk extensions are accepted and ignored.
+/// - `read_eof`: Handles EOF by returning an error, since the body can't be complete before
+///   the zero-size chunk and its trailer have been read.
 ///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{ChunkedReader, Reader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
 /// let mut reader = ChunkedReader::new();
-/// let mut buffer = ReceiveBuffer { data: vec![1, 2, 3] };
-/// 
-/// // Reading chunked data from the buffer.
-/// if let Some(data) = reader.read(&mut buffer) {
-///     println!("{:?}", data);
+/// let mut buf = ReceiveBuffer::new();
+/// buf.append(b"5;name=value\r\nhello\r\n0\r\nExpires: never\r\n\r\n");
+///
+/// match reader.read(&mut buf) {
+///     Ok(Some(Event::Data(data))) => {
+///         assert_eq!(&data.data[..], b"hello");
+///         assert!(data.chunk_start && data.chunk_end);
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// match reader.read(&mut buf) {
+///     Ok(Some(Event::EndOfMessage(eom))) => {
+///         assert_eq!(eom.headers.get_comma_header(b"expires"), vec![b"never".to_vec()]);
+///     }
+///     other => panic!("unexpected {:?}", other),
 /// }
-/// 
+///
 /// // Handling EOF error.
-/// match reader.read_eof() {
-///     Ok(end_of_message) => println!("End of message: {:?}", end_of_message),
-///     Err(e) => println!("Error: {}", e),
+/// assert!(reader.read_eof().is_err());
+///
+/// // A chunk's size line and its data may arrive separately, and its data in pieces
+/// let mut reader = ChunkedReader::new();
+/// let mut buf = ReceiveBuffer::new();
+/// buf.append(b"5\r\n");
+/// assert!(reader.read(&mut buf).unwrap().is_none());
+/// let mut flags = Vec::new();
+/// for piece in [&b"hel"[..], b"lo"] {
+///     buf.append(piece);
+///     match reader.read(&mut buf) {
+///         Ok(Some(Event::Data(data))) => flags.push((data.chunk_start, data.chunk_end)),
+///         other => panic!("unexpected {:?}", other),
+///     }
 /// }
+/// assert_eq!(flags, vec![(true, false), (false, true)]);
+///
+/// // Trailers can't carry framing or routing fields
+/// let mut buf = ReceiveBuffer::new();
+/// buf.append(b"0\r\nContent-Length: 5\r\n\r\n");
+/// assert!(ChunkedReader::new().read(&mut buf).is_err());
 /// ```
 impl Reader for ChunkedReader {
-    fn read(&mut self, buf: &mut ReceiveBuffer) -> Option<Data> {
+    fn set_allow_obs_fold(&mut self, allow_obs_fold: bool) {
+        self.allow_obs_fold = allow_obs_fold;
+    }
+
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
         if self.reading_trailer {
-            let lines = buf.maybe_extract_lines();
-            if let Some(lines) = lines {
-                return Some(Data {
-                    data: vec![], // Placeholder for actual header parsing
-                    chunk_start: false,
-                    chunk_end: false,
-                });
-            }
+            let lines = match buf.maybe_extract_lines() {
+                Some(lines) => lines,
+                None => return Ok(None),
+            };
+            let headers = decode_header_lines(&lines, self.allow_obs_fold)?;
+            check_trailer_fields(&headers)?;
+            return Ok(Some(Event::EndOfMessage(EndOfMessage { headers })));
         }
         if self.bytes_to_discard > 0 {
-            let data = buf.maybe_extract_at_most(self.bytes_to_discard);
-            if data.is_none() {
-                return None;
+            let data = match buf.maybe_extract_at_most(self.bytes_to_discard) {
+                Some(data) => data,
+                None => return Ok(None),
+            };
+            // The CRLF after the chunk data must be exactly that, or the peer's idea of where
+            // the chunk ends differs from ours
+            let expected = &b"\r\n"[2 - self.bytes_to_discard..][..data.len()];
+            if data[..] != *expected {
+                return Err(ProtocolError::remote(
+                    ErrorKind::BadChunk,
+                    "missing CRLF after chunk data",
+                ));
             }
-            self.bytes_to_discard -= data.unwrap().len();
+            self.bytes_to_discard -= data.len();
             if self.bytes_to_discard > 0 {
-                return None;
+                return Ok(None);
             }
         }
 
         if self.bytes_in_chunk == 0 {
-            // Simulating chunk header processing
-            self.bytes_in_chunk = 100; // Simulated chunk size for demonstration
+            let line = match buf.maybe_extract_next_line() {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            let chunk_size = grammar::parse_chunk_header(&line)
+                .ok_or_else(|| bad_line(ErrorKind::BadChunk, "illegal chunk header:", &line))?;
+            // The grammar only lets hex digits through, so this can only fail by overflowing
+            self.bytes_in_chunk = std::str::from_utf8(chunk_size)
+                .ok()
+                .and_then(|size| usize::from_str_radix(size, 16).ok())
+                .ok_or_else(|| ProtocolError::remote(ErrorKind::BadChunk, "chunk size too large"))?;
+            if self.bytes_in_chunk == 0 {
+                self.reading_trailer = true;
+                return self.read(buf);
+            }
+            // The data may not be here yet, so remember that whatever comes next starts a chunk
+            self.pending_chunk_start = true;
         }
 
-        if self.bytes_in_chunk > 0 {
-            let data = buf.maybe_extract_at_most(self.bytes_in_chunk);
-            match data {
-                Some(data) => {
-                    self.bytes_in_chunk -= data.len();
-                    Some(Data {
-                        data,
-                        chunk_start: true,
-                        chunk_end: self.bytes_in_chunk == 0,
-                    })
-                }
-                None => None,
-            }
-        } else {
-            None
+        let data = match buf.maybe_extract_at_most(self.bytes_in_chunk) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        self.bytes_in_chunk -= data.len();
+        let chunk_start = std::mem::take(&mut self.pending_chunk_start);
+        let chunk_end = self.bytes_in_chunk == 0;
+        if chunk_end {
+            self.bytes_to_discard = 2;
         }
+        Ok(Some(Event::Data(Data::new(data, chunk_start, chunk_end))))
     }
 
-    fn read_eof(&mut self) -> Result<EndOfMessage, RemoteProtocolError> {
-        Err(RemoteProtocolError(
-            "peer closed connection without sending complete message body".into(),
+    fn read_eof(&mut self) -> Result<Event, ProtocolError> {
+        Err(ProtocolError::remote(
+            ErrorKind::IncompleteMessage,
+            "peer closed connection without sending complete message body: incomplete chunked read",
         ))
     }
+    fn oversized_error(&self) -> ProtocolError {
+        if self.reading_trailer {
+            return ProtocolError::new_remote(ErrorKind::OversizedHead, "trailer block too long", 431);
+        }
+        ProtocolError::new_remote(ErrorKind::OversizedChunkLine, "chunk header too long", 400)
+    }
 }
-/// Implements the `Reader` trait for `Http10Reader`.
-/// 
+/// Implements the `Reader` trait for `Http10Reader`, which reads a body that runs until the
+/// peer closes the connection.
+///
 /// # Methods:
-/// - `read`: Reads data from the buffer with a large fixed length (999999999 bytes).
-/// - `read_eof`: Returns an `EndOfMessage` when EOF is reached.
+/// - `read`: Hands out whatever is buffered as `Data`, without waiting for more.
+/// - `read_eof`: Returns an `EndOfMessage` when EOF is reached, since closing the connection is
+///   how this kind of body ends. A body cut short by EOF can only happen with explicit framing,
+///   and `ContentLengthReader` and `ChunkedReader` report that as an error instead.
 ///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::events::Event;
+/// use yo::http1::readers::{Http10Reader, Reader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
 /// let mut reader = Http10Reader;
-/// let mut buffer = ReceiveBuffer { data: vec![1, 2, 3] };
-/// 
-/// // Reading data from the buffer.
-/// if let Some(data) = reader.read(&mut buffer) {
-///     println!("{:?}", data);
+/// let mut buffer = ReceiveBuffer::new();
+/// assert!(reader.read(&mut buffer).unwrap().is_none());
+///
+/// buffer.append(b"partial");
+/// match reader.read(&mut buffer).unwrap() {
+///     Some(Event::Data(data)) => assert_eq!(&data.data[..], b"partial"),
+///     other => panic!("unexpected {:?}", other),
 /// }
-/// 
+///
 /// // Handling EOF.
-/// match reader.read_eof() {
-///     Ok(end_of_message) => println!("End of message: {:?}", end_of_message),
-///     Err(e) => println!("Error: {}", e),
-/// }
+/// assert!(matches!(reader.read_eof(), Ok(Event::EndOfMessage(_))));
+/// ```
+///
+/// Through a connection, the body streams as it arrives and ends when the peer closes:
+///
+/// ```rust
+/// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Request, Role, Sentinel};
+///
+/// let mut connection = Connection::new(Role::Client, 16 * 1024);
+/// let request = Request::new(b"GET".to_vec(), b"/".to_vec(), vec![(b"Host".to_vec(), b"a".to_vec())], b"1.1".to_vec()).unwrap();
+/// connection.send(Event::Request(request)).unwrap();
+/// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+///
+/// connection.receive_data(b"HTTP/1.0 200 OK\r\n\r\nfirst").unwrap();
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Response(_)))));
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(d))) if &d.data[..] == b"first"));
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Sentinel(Sentinel::NeedData))));
+///
+/// connection.receive_data(b"second").unwrap();
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(d))) if &d.data[..] == b"second"));
+/// connection.receive_data(b"").unwrap();
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::EndOfMessage(_)))));
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::ConnectionClosed(_)))));
+/// ```
+///
+/// Whereas a `Content-Length` body that EOF cuts short is an error:
+///
+/// ```rust
+/// use yo::http1::{Connection, EndOfMessage, Event, NextEvent, Request, Role};
+///
+/// let mut connection = Connection::new(Role::Client, 16 * 1024);
+/// let request = Request::new(b"GET".to_vec(), b"/".to_vec(), vec![(b"Host".to_vec(), b"a".to_vec())], b"1.1".to_vec()).unwrap();
+/// connection.send(Event::Request(request)).unwrap();
+/// connection.send(Event::EndOfMessage(EndOfMessage::default())).unwrap();
+///
+/// connection.receive_data(b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\nfirst").unwrap();
+/// connection.receive_data(b"").unwrap();
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Response(_)))));
+/// assert!(matches!(connection.next_event(), Ok(NextEvent::Event(Event::Data(_)))));
+/// assert!(connection.next_event().is_err());
 /// ```
 pub struct Http10Reader;
 
 impl Reader for Http10Reader {
-    fn read(&mut self, buf: &mut ReceiveBuffer) -> Option<Data> {
-        let data = buf.maybe_extract_at_most(999999999);
-        data.map(|data| Data {
-            data,
-            chunk_start: false,
-            chunk_end: false,
-        })
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
+        Ok(buf
+            .maybe_extract_at_most(usize::MAX)
+            .map(|data| Event::Data(Data::new(data, false, false))))
     }
 
-    fn read_eof(&mut self) -> Result<EndOfMessage, RemoteProtocolError> {
-        Ok(EndOfMessage {
-            headers: vec![], // Simplified for demonstration
-        })
+    fn read_eof(&mut self) -> Result<Event, ProtocolError> {
+        Ok(Event::EndOfMessage(EndOfMessage::default()))
     }
 }
-/// Type alias for a hashmap storing reader objects, keyed by a tuple of strings.
-/// 
+/// Fails if the peer sent anything when no data was expected.
+///
 /// # Example
-/// 
+///
 /// ```rust
-/// let mut readers: ReadersType = HashMap::new();
-/// readers.insert(("CLIENT".into(), "IDLE".into()), Box::new(ContentLengthReader::new(100)));
+/// use yo::http1::readers::expect_nothing;
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut buffer = ReceiveBuffer::new();
+/// assert!(expect_nothing(&mut buffer).is_ok());
+/// buffer.append(b"x");
+/// assert!(expect_nothing(&mut buffer).is_err());
+/// ```
+pub fn expect_nothing(buf: &mut ReceiveBuffer) -> Result<(), ProtocolError> {
+    if !buf.is_empty() {
+        return Err(ProtocolError::remote(ErrorKind::UnexpectedData, "Got data when expecting EOF"));
+    }
+    Ok(())
+}
+/// Reader for states where the peer has nothing more to send, e.g. after its `EndOfMessage`.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::readers::{ExpectNothingReader, Reader};
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut buffer = ReceiveBuffer::new();
+/// assert!(matches!(ExpectNothingReader.read(&mut buffer), Ok(None)));
+/// buffer.append(b"x");
+/// assert!(ExpectNothingReader.read(&mut buffer).is_err());
 /// ```
-pub fn expect_nothing(buf: &mut ReceiveBuffer) {
-    if !buf.data.is_empty() {
-        panic!("Got data when expecting EOF");
+pub struct ExpectNothingReader;
+
+impl Reader for ExpectNothingReader {
+    fn read(&mut self, buf: &mut ReceiveBuffer) -> Result<Option<Event>, ProtocolError> {
+        expect_nothing(buf)?;
+        Ok(None)
     }
 }
-/// Creates and returns a `ReadersType` hashmap with predefined reader instances.
-/// 
+/// Builds a fresh reader; the flag says whether obs-fold is allowed in the heads it reads.
+pub type ReaderFactory = fn(bool) -> Box<dyn Reader>;
+/// Dispatch table from a `(role, state)` pair of `state::Sentinel`s to the reader for whatever
+/// that role can send while in that state.
+///
+/// `SendBody` is deliberately missing: how a body is read depends on the framing of the head
+/// before it, so `reader_for` builds those readers with `body_reader` instead. States in which
+/// nothing can be read at all, such as after a protocol switch, are missing too.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use yo::http1::readers::{ExpectNothingReader, Reader, ReadersType};
+/// use yo::http1::state::Sentinel;
+///
+/// let mut readers: ReadersType = HashMap::new();
+/// readers.insert((Sentinel::Client, Sentinel::Done), |_| Box::new(ExpectNothingReader) as Box<dyn Reader>);
+/// ```
+pub type ReadersType = HashMap<(Sentinel, Sentinel), ReaderFactory>;
+/// Creates and returns the `ReadersType` table the connection dispatches on.
+///
 /// # Example
-/// 
+///
 /// ```rust
+/// use yo::http1::readers::build_readers;
+/// use yo::http1::state::Sentinel;
+///
 /// let readers = build_readers();
 /// // Access a reader for a specific state.
-/// let reader = readers.get(&("CLIENT".into(), "IDLE".into()));
+/// let reader = readers.get(&(Sentinel::Client, Sentinel::Idle)).map(|make| make(false));
+/// assert!(reader.is_some());
+/// assert!(readers.get(&(Sentinel::Client, Sentinel::SendBody)).is_none());
 /// ```
-pub type ReadersType = HashMap<(String, String), Box<dyn Reader>>;
-
 pub fn build_readers() -> ReadersType {
     let mut readers: ReadersType = HashMap::new();
 
-    readers.insert(("CLIENT".into(), "IDLE".into()), Box::new(ContentLengthReader::new(100)));
-    readers.insert(("SERVER".into(), "IDLE".into()), Box::new(ChunkedReader::new()));
-    readers.insert(("SERVER".into(), "SEND_RESPONSE".into()), Box::new(Http10Reader));
+    readers.insert((Sentinel::Client, Sentinel::Idle), |allow_obs_fold| {
+        Box::new(RequestHeadReader::default().with_obs_fold(allow_obs_fold))
+    });
+    // A server may answer before it has seen a request, e.g. with a 408
+    readers.insert((Sentinel::Server, Sentinel::Idle), |allow_obs_fold| {
+        Box::new(ResponseHeadReader::default().with_obs_fold(allow_obs_fold))
+    });
+    readers.insert((Sentinel::Server, Sentinel::SendResponse), |allow_obs_fold| {
+        Box::new(ResponseHeadReader::default().with_obs_fold(allow_obs_fold))
+    });
+    for role in [Sentinel::Client, Sentinel::Server] {
+        for state in [Sentinel::Done, Sentinel::MustClose, Sentinel::Closed] {
+            readers.insert((role, state), |_| Box::new(ExpectNothingReader));
+        }
+    }
 
     readers
 }
 
+lazy_static! {
+    /// The readers for every state but `SendBody`, built once.
+    pub static ref READERS: ReadersType = build_readers();
+}
+
+/// Builds the reader for a body framed as `framing`.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::events::Event;
+/// use yo::http1::framing::Framing;
+/// use yo::http1::readers::body_reader;
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
+/// let mut reader = body_reader(Framing::NoBody, false);
+/// assert!(matches!(reader.read(&mut ReceiveBuffer::new()), Ok(Some(Event::EndOfMessage(_)))));
+/// ```
+pub fn body_reader(framing: Framing, allow_obs_fold: bool) -> Box<dyn Reader> {
+    match framing {
+        Framing::NoBody => Box::new(ContentLengthReader::new(0)),
+        Framing::ContentLength(length) => Box::new(ContentLengthReader::new(length)),
+        Framing::Chunked => Box::new(ChunkedReader::new().with_obs_fold(allow_obs_fold)),
+        Framing::ReadUntilClose => Box::new(Http10Reader),
+    }
+}
+
+/// Picks the reader for what `role` sends while in `state`.
+///
+/// # Arguments
+/// * `role` - `Sentinel::Client` or `Sentinel::Server`.
+/// * `state` - The state `role` is in.
+/// * `framing` - The framing of the head that moved `role` into `SendBody`, if it did.
+/// * `allow_obs_fold` - Whether folded header lines are unfolded rather than rejected.
+///
+/// # Returns
+/// The reader, or `None` if nothing can be read from `role` in `state`.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::framing::Framing;
+/// use yo::http1::readers::reader_for;
+/// use yo::http1::state::Sentinel;
+///
+/// assert!(reader_for(Sentinel::Client, Sentinel::SendBody, Some(Framing::Chunked), false).is_some());
+/// assert!(reader_for(Sentinel::Client, Sentinel::SwitchedProtocol, None, false).is_none());
+/// ```
+pub fn reader_for(
+    role: Sentinel,
+    state: Sentinel,
+    framing: Option<Framing>,
+    allow_obs_fold: bool,
+) -> Option<Box<dyn Reader>> {
+    if state == Sentinel::SendBody {
+        return framing.map(|framing| body_reader(framing, allow_obs_fold));
+    }
+    READERS.get(&(role, state)).map(|make| make(allow_obs_fold))
+}
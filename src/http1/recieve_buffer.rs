@@ -1,33 +1,111 @@
-use std::collections::VecDeque;
-use std::str;
-/// Regular expression pattern for matching blank lines (two consecutive newlines).
-/// 
-/// # Example
-/// 
-/// ```rust
-/// let regex = Regex::new(BLANK_LINE_REGEX).unwrap();
-/// assert!(regex.is_match("\r\n\r\n"));
-/// ```
-const BLANK_LINE_REGEX: &str = r"\r?\n\r?\n";  // Use regex for matching blank lines
+use super::events::{share_payload, Payload};
+
+/// The bytes of a `ReceiveBuffer`, kept contiguous.
+///
+/// Extracting only moves `start` forward; the space in front of it is reclaimed in bulk by
+/// `extend` once it makes up half the allocation, so draining is amortized O(1) per byte.
+#[cfg(not(feature = "bytes"))]
+#[derive(Default)]
+struct Storage {
+    data: Vec<u8>,
+    start: usize,
+}
+
+#[cfg(not(feature = "bytes"))]
+impl Storage {
+    fn extend(&mut self, bytes: &[u8]) {
+        // Reclaim the extracted prefix once it is at least half the buffer, which keeps both the
+        // memory overhead and the cost of moving the rest down proportional to what was read
+        if self.start > 0 && self.start * 2 >= self.data.len() {
+            self.data.drain(..self.start);
+            self.start = 0;
+        }
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[self.start..]
+    }
+
+    fn split_to(&mut self, count: usize) -> Payload {
+        let out = self.data[self.start..self.start + count].to_vec();
+        self.start += count;
+        if self.start == self.data.len() {
+            self.data.clear();
+            self.start = 0;
+        }
+        out
+    }
+}
+
+/// The bytes of a `ReceiveBuffer`, kept contiguous.
+///
+/// Extracted pieces are frozen views into the same allocation, so handing out a body costs a
+/// reference count rather than a copy; `BytesMut` reclaims the space once they are dropped.
+#[cfg(feature = "bytes")]
+#[derive(Default)]
+struct Storage {
+    data: bytes::BytesMut,
+}
+
+#[cfg(feature = "bytes")]
+impl Storage {
+    fn extend(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn split_to(&mut self, count: usize) -> Payload {
+        self.data.split_to(count).freeze()
+    }
+}
+
 /// A buffer for receiving data, with efficient operations for appending, extracting, and searching.
 ///
+/// The unread bytes are always contiguous, and extracting them never copies more than what is
+/// handed out. With the `bytes` feature, not even that: bodies and head lines come out as
+/// `Bytes` sharing the buffer's allocation.
+///
+/// Line searches remember how far they got. When a head trickles in a few bytes at a time, each
+/// call only looks at what arrived since the last one, rather than rescanning from the start.
+/// Lines may end in either CRLF or a bare LF.
+///
 /// # Fields:
-/// - `data`: A deque to hold the buffered data.
-/// - `next_line_search`: Index for searching the next line.
-/// - `multiple_lines_search`: Index for searching multiple lines.
+/// - `data`: The unread bytes.
+/// - `next_line_search`: Where the next search for a line ending resumes.
+/// - `multiple_lines_search`: Where the next search for a blank line resumes.
+/// - `consumed`: Total number of bytes extracted so far, i.e. the stream offset of the first
+///   unread byte.
 ///
 /// # Example
 /// 
 /// ```rust
+/// use yo::http1::recieve_buffer::ReceiveBuffer;
+///
 /// let mut buffer = ReceiveBuffer::new();
 /// buffer.append(b"Hello\r\nWorld\r\n\r\n");
-/// let lines = buffer.maybe_extract_lines();
-/// assert_eq!(lines, Some(vec![b"Hello".to_vec(), b"World".to_vec()]));
+/// let lines = buffer.maybe_extract_lines().unwrap();
+/// assert_eq!(lines, [&b"Hello"[..], &b"World"[..]]);
+///
+/// // A head that arrives one byte at a time, with bare LF line endings
+/// for &byte in b"GET / HTTP/1.0\nHost: a\n" {
+///     buffer.append(&[byte]);
+///     assert_eq!(buffer.maybe_extract_lines(), None);
+/// }
+/// buffer.append(b"\nbody");
+/// let lines = buffer.maybe_extract_lines().unwrap();
+/// assert_eq!(lines, [&b"GET / HTTP/1.0"[..], &b"Host: a"[..]]);
+/// assert_eq!(buffer.as_bytes(), b"body");
 /// ```
+#[derive(Default)]
 pub struct ReceiveBuffer {
-    data: VecDeque<u8>,  // Using VecDeque for efficient removal from the front
+    data: Storage,
     next_line_search: usize,
     multiple_lines_search: usize,
+    consumed: usize,
 }
 
 impl ReceiveBuffer {
@@ -36,14 +114,17 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let buffer = ReceiveBuffer::new();
     /// assert!(buffer.is_empty());
     /// ```
     pub fn new() -> Self {
         Self {
-            data: VecDeque::new(),
+            data: Storage::default(),
             next_line_search: 0,
             multiple_lines_search: 0,
+            consumed: 0,
         }
     }
 
@@ -52,12 +133,14 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello");
     /// assert_eq!(buffer.len(), 5);
     /// ```
     pub fn append(&mut self, bytes: &[u8]) {
-        self.data.extend(bytes); 
+        self.data.extend(bytes);
     }
 
     /// Checks if the buffer is empty.
@@ -65,11 +148,13 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let buffer = ReceiveBuffer::new();
     /// assert!(buffer.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.len() == 0
     }
 
     /// Returns the length of the buffer.
@@ -77,12 +162,14 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello");
     /// assert_eq!(buffer.len(), 5);
     /// ```
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.data.as_bytes().len()
     }
 
     /// Returns the buffer data as a byte slice.
@@ -90,31 +177,54 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello");
     /// assert_eq!(buffer.as_bytes(), b"Hello");
     /// ```
     pub fn as_bytes(&self) -> &[u8] {
-        &self.data.as_slices().0
+        self.data.as_bytes()
     }
 
-    /// Attempts to extract at most `count` bytes from the buffer.
-    /// 
+    /// Returns a copy of everything still in the buffer.
+    ///
     /// # Example
-    /// 
+    ///
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello");
-    /// let data = buffer.maybe_extract_at_most(3);
-    /// assert_eq!(data, Some(b"Hel".to_vec()));
+    /// buffer.append(b" World");
+    /// assert_eq!(buffer.to_vec(), b"Hello World");
     /// ```
-    fn extract(&mut self, count: usize) -> Vec<u8> {
-        let mut out = Vec::with_capacity(count);
-        for _ in 0..count {
-            if let Some(byte) = self.data.pop_front() {
-                out.push(byte);
-            }
-        }
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    /// Returns how many bytes have been extracted from the buffer since it was created, which is
+    /// the offset in the received stream of the first byte still buffered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
+    /// let mut buffer = ReceiveBuffer::new();
+    /// buffer.append(b"Hello\r\nWorld");
+    /// buffer.maybe_extract_next_line();
+    /// assert_eq!(buffer.consumed(), 7);
+    /// ```
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Removes and returns the first `count` bytes of the buffer.
+    fn extract(&mut self, count: usize) -> Payload {
+        let count = count.min(self.len());
+        let out = self.data.split_to(count);
+        self.consumed += count;
 
         self.next_line_search = 0;
         self.multiple_lines_search = 0;
@@ -123,85 +233,120 @@ impl ReceiveBuffer {
     }
 
     /// Try to extract at most 'count' bytes from the buffer
-    pub fn maybe_extract_at_most(&mut self, count: usize) -> Option<Vec<u8>> {
-        if self.data.len() < count {
+    ///
+    /// Returns whatever is available up to `count` bytes, or `None` if the buffer is empty.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
+    /// let mut buffer = ReceiveBuffer::new();
+    /// buffer.append(b"Hello");
+    /// let data = buffer.maybe_extract_at_most(3);
+    /// assert_eq!(data.as_deref(), Some(&b"Hel"[..]));
+    /// assert_eq!(buffer.maybe_extract_at_most(10).as_deref(), Some(&b"lo"[..]));
+    /// assert_eq!(buffer.maybe_extract_at_most(10), None);
+    /// ```
+    pub fn maybe_extract_at_most(&mut self, count: usize) -> Option<Payload> {
+        if self.is_empty() {
             return None;
         }
 
         Some(self.extract(count))
     }
 
-    /// Attempts to extract the next complete line (ends with `\r\n` or `\n`).
+    /// Attempts to extract the next complete line (ends with `\r\n` or `\n`), line ending
+    /// included.
     /// 
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello\r\nWorld");
     /// let line = buffer.maybe_extract_next_line();
-    /// assert_eq!(line, Some(b"Hello".to_vec()));
+    /// assert_eq!(line.as_deref(), Some(&b"Hello\r\n"[..]));
+    ///
+    /// assert_eq!(buffer.maybe_extract_next_line(), None);
+    /// buffer.append(b"\n");
+    /// assert_eq!(buffer.maybe_extract_next_line().as_deref(), Some(&b"World\n"[..]));
     /// ```
-    pub fn maybe_extract_next_line(&mut self) -> Option<Vec<u8>> {
-        let search_start_index = self.next_line_search.saturating_sub(1);
-        if let Some(pos) = self.find_line(search_start_index) {
-            return Some(self.extract(pos + 2));  // skip \r\n
+    pub fn maybe_extract_next_line(&mut self) -> Option<Payload> {
+        match self.find_line(self.next_line_search) {
+            Some(pos) => Some(self.extract(pos + 1)),
+            None => {
+                self.next_line_search = self.len();
+                None
+            }
         }
-        None
     }
 
     /// Attempts to extract lines up to the first blank line.
+    ///
+    /// The lines are pieces of one extracted block, so with the `bytes` feature they all share
+    /// its allocation and nothing is copied.
     /// 
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(b"Hello\r\nWorld\r\n\r\n");
-    /// let lines = buffer.maybe_extract_lines();
-    /// assert_eq!(lines, Some(vec![b"Hello".to_vec(), b"World".to_vec()]));
+    /// let lines = buffer.maybe_extract_lines().unwrap();
+    /// assert_eq!(lines, [&b"Hello"[..], &b"World"[..]]);
     /// ```
-    pub fn maybe_extract_lines(&mut self) -> Option<Vec<Vec<u8>>> {
+    pub fn maybe_extract_lines(&mut self) -> Option<Vec<Payload>> {
         // Handle immediate empty lines
-        if self.data.len() > 0 && self.data[0] == b'\n' {
+        let bytes = self.as_bytes();
+        if bytes.starts_with(b"\n") {
             self.extract(1);
             return Some(vec![]);
         }
 
-        if self.data.len() > 1 && self.data[0] == b'\r' && self.data[1] == b'\n' {
+        if bytes.starts_with(b"\r\n") {
             self.extract(2);
             return Some(vec![]);
         }
 
         // Look for a blank line
-        if let Some(pos) = self.find_blank_line() {
-            let extracted_data = self.extract(pos);
-            let lines = self.split_into_lines(extracted_data);
-            return Some(lines);
+        match self.find_blank_line(self.multiple_lines_search) {
+            Some(end) => {
+                let extracted_data = self.extract(end);
+                Some(split_into_lines(&extracted_data))
+            }
+            None => {
+                // The terminator can straddle what we have and what comes next, so the next
+                // search backs up over the last two bytes
+                self.multiple_lines_search = self.len().saturating_sub(2);
+                None
+            }
         }
-
-        None
-    }
-
-    /// Helper function to search for the next line in the buffer
-    fn find_line(&self, start: usize) -> Option<usize> {
-        // CHANGE: Use `as_slices` to convert `VecDeque` to a slice, and apply `windows` to look for "\r\n"
-        self.data.as_slices().0.windows(2)  // Accessing slice using `as_slices()`
-            .position(|window| window == b"\r\n")
-            .map(|pos| start + pos)
     }
 
-    /// Helper function to find the first blank line
-    fn find_blank_line(&self) -> Option<usize> {
-        let data_str = str::from_utf8(&self.data.as_slices().0).ok()?;  
-        data_str.find("\r\n\r\n").map(|pos| pos + 4)    
+    /// Returns the index of the first `\n` at or after `from`.
+    fn find_line(&self, from: usize) -> Option<usize> {
+        let bytes = self.as_bytes();
+        bytes.get(from..)?.iter().position(|&b| b == b'\n').map(|pos| from + pos)
     }
 
-    /// Split the data into lines by \n
-    fn split_into_lines(&self, data: Vec<u8>) -> Vec<Vec<u8>> {
-        let data_str = str::from_utf8(&data).expect("Invalid UTF-8 data");
-        data_str
-            .split('\n')
-            .map(|line| line.as_bytes().to_vec())
-            .collect()
+    /// Returns the index just past the first blank line at or after `from`, that is the end
+    /// of the first `\n\n` or `\n\r\n`.
+    fn find_blank_line(&self, from: usize) -> Option<usize> {
+        // Search bytes rather than text: whatever follows the head (a body, or data for a
+        // protocol we are switching to) needn't be UTF-8
+        let bytes = self.as_bytes();
+        let mut pos = from;
+        while let Some(newline) = self.find_line(pos) {
+            match &bytes[newline + 1..] {
+                [b'\n', ..] => return Some(newline + 2),
+                [b'\r', b'\n', ..] => return Some(newline + 3),
+                _ => pos = newline + 1,
+            }
+        }
+        None
     }
 
     /// Checks if the next line is an obviously invalid request line (non-printable characters).
@@ -209,16 +354,28 @@ impl ReceiveBuffer {
     /// # Example
     /// 
     /// ```rust
+    /// use yo::http1::recieve_buffer::ReceiveBuffer;
+    ///
     /// let mut buffer = ReceiveBuffer::new();
     /// buffer.append(&[0x10]); // Non-printable character
     /// assert!(buffer.is_next_line_obviously_invalid_request_line());
     /// ```
     pub fn is_next_line_obviously_invalid_request_line(&self) -> bool {
-        if let Some(&first_byte) = self.data.get(0) {
+        if let Some(&first_byte) = self.as_bytes().first() {
             first_byte < 0x21  // Check for non-printable characters
         } else {
             false
         }
     }
 }
-
+/// Splits a block of lines on `\n`, dropping the `\r` of each CRLF and the blank line that
+/// terminated the block.
+fn split_into_lines(data: &Payload) -> Vec<Payload> {
+    let mut lines: Vec<Payload> = data
+        .split(|&b| b == b'\n')
+        .map(|line| share_payload(data, line.strip_suffix(b"\r").unwrap_or(line)))
+        .collect();
+    // The block ends with a blank line, which leaves two empty entries at the end
+    lines.truncate(lines.len().saturating_sub(2));
+    lines
+}
@@ -0,0 +1,218 @@
+//! Hand-written, table-driven byte scanners for the HTTP/1.1 grammar.
+//!
+//! Every byte is classified once, through a 256-entry table of character classes, so each
+//! scanner is a single pass over its input without backtracking or allocation. They accept
+//! exactly what the regular expressions in `http_regex` accept; see [`super::grammar`] for how
+//! one or the other gets picked.
+//!
+//! # Example
+//!
+//! ```rust
+//! use yo::http1::scanner;
+//!
+//! let line = scanner::parse_status_line(b"HTTP/1.1 404 Not Found").unwrap();
+//! assert_eq!(line.status_code, 404);
+//! assert_eq!(line.reason, b"Not Found");
+//! assert_eq!(scanner::parse_chunk_header(b"1a;name=value\r\n"), Some(&b"1a"[..]));
+//! assert_eq!(scanner::parse_chunk_header(b"1 ; name = \"a b\" \r\n"), Some(&b"1"[..]));
+//! ```
+//!
+//! When the regex grammar is compiled in too, the two are checked against each other on a
+//! corpus of generated lines:
+//!
+//! ```rust
+//! # #[cfg(feature = "regex")]
+//! # {
+//! use yo::http1::{http_regex, scanner};
+//!
+//! let alphabet: &[u8] = b"GET /HTP1.0-:;aZ9f \t\r\n\x00\x0b\x0c\x7f\x80\xff";
+//! let prefixes: &[&[u8]] = &[b"", b"GET / HTTP/1.", b"HTTP/1.1 2", b"Host:", b"a", b"0"];
+//! let mut seed: u32 = 0x2545_f491;
+//! let mut next = move || {
+//!     seed ^= seed << 13;
+//!     seed ^= seed >> 17;
+//!     seed ^= seed << 5;
+//!     seed as usize
+//! };
+//! for _ in 0..20_000 {
+//!     let mut line = prefixes[next() % prefixes.len()].to_vec();
+//!     for _ in 0..next() % 12 {
+//!         line.push(alphabet[next() % alphabet.len()]);
+//!     }
+//!     let line = &line[..];
+//!     assert_eq!(scanner::parse_request_line(line), http_regex::parse_request_line(line), "{:?}", line);
+//!     assert_eq!(scanner::parse_status_line(line), http_regex::parse_status_line(line), "{:?}", line);
+//!     assert_eq!(scanner::parse_header_field(line), http_regex::parse_header_field(line), "{:?}", line);
+//!     assert_eq!(scanner::parse_chunk_header(line), http_regex::parse_chunk_header(line), "{:?}", line);
+//!     assert_eq!(scanner::is_token(line), http_regex::is_token(line), "{:?}", line);
+//!     assert_eq!(scanner::is_request_target(line), http_regex::is_request_target(line), "{:?}", line);
+//!     assert_eq!(scanner::is_field_value(line), http_regex::is_field_value(line), "{:?}", line);
+//!     assert_eq!(scanner::is_content_length(line), http_regex::is_content_length(line), "{:?}", line);
+//! }
+//! # }
+//! ```
+
+use super::grammar::{HeaderField, RequestLine, StatusLine};
+
+/// `tchar`, the bytes a token (a method or a header name) is made of.
+const TOKEN: u8 = 1 << 0;
+/// Visible ASCII, the bytes a request target is made of.
+const VCHAR: u8 = 1 << 1;
+/// Visible ASCII or obs-text: anything but NUL and ASCII whitespace.
+const FIELD_VCHAR: u8 = 1 << 2;
+/// Space and horizontal tab.
+const OWS: u8 = 1 << 3;
+const DIGIT: u8 = 1 << 4;
+const HEXDIG: u8 = 1 << 5;
+
+/// The character classes of every byte value.
+static CLASSES: [u8; 256] = build_classes();
+
+const fn build_classes() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let b = i as u8;
+        let mut class = 0;
+        if b.is_ascii_alphanumeric()
+            || matches!(
+                b,
+                b'-' | b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'.' | b'^' | b'_'
+                    | b'`' | b'|' | b'~'
+            )
+        {
+            class |= TOKEN;
+        }
+        if b >= 0x21 && b <= 0x7e {
+            class |= VCHAR;
+        }
+        if !matches!(b, 0x00 | b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ') {
+            class |= FIELD_VCHAR;
+        }
+        if b == b' ' || b == b'\t' {
+            class |= OWS;
+        }
+        if b.is_ascii_digit() {
+            class |= DIGIT;
+        }
+        if b.is_ascii_hexdigit() {
+            class |= HEXDIG;
+        }
+        table[i] = class;
+        i += 1;
+    }
+    table
+}
+
+/// Returns how many leading bytes of `bytes` belong to one of `classes`.
+fn span(bytes: &[u8], classes: u8) -> usize {
+    bytes.iter().position(|&b| CLASSES[b as usize] & classes == 0).unwrap_or(bytes.len())
+}
+
+/// Checks that `bytes` is non-empty and made only of bytes in `classes`.
+fn all(bytes: &[u8], classes: u8) -> bool {
+    !bytes.is_empty() && span(bytes, classes) == bytes.len()
+}
+
+/// Strips leading and trailing optional whitespace.
+fn trim_ows(bytes: &[u8]) -> &[u8] {
+    let start = span(bytes, OWS);
+    let end = bytes.iter().rposition(|&b| CLASSES[b as usize] & OWS == 0).map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+/// Splits an `HTTP/x.y` prefix off `bytes`, returning the `x.y` and what follows it.
+fn split_http_version(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = bytes.strip_prefix(b"HTTP/")?;
+    match rest {
+        [major, b'.', minor, ..] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            Some((&rest[..3], &rest[3..]))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a request line: `method SP request-target SP HTTP-version`.
+pub fn parse_request_line(line: &[u8]) -> Option<RequestLine<'_>> {
+    let method_len = span(line, TOKEN);
+    let after_method = line[method_len..].strip_prefix(b" ").filter(|_| method_len > 0)?;
+    let target_len = span(after_method, VCHAR);
+    let after_target = after_method[target_len..].strip_prefix(b" ").filter(|_| target_len > 0)?;
+    match split_http_version(after_target)? {
+        (http_version, []) => Some(RequestLine {
+            method: &line[..method_len],
+            target: &after_method[..target_len],
+            http_version,
+        }),
+        _ => None,
+    }
+}
+
+/// Parses a status line: `HTTP-version SP status-code [SP reason-phrase]`.
+pub fn parse_status_line(line: &[u8]) -> Option<StatusLine<'_>> {
+    let (http_version, rest) = split_http_version(line)?;
+    let rest = rest.strip_prefix(b" ")?;
+    if rest.len() < 3 || span(&rest[..3], DIGIT) != 3 {
+        return None;
+    }
+    let status_code = rest[..3].iter().fold(0u16, |code, digit| code * 10 + u16::from(digit - b'0'));
+    let reason = match &rest[3..] {
+        [] => &[][..],
+        [b' ', reason @ ..] if span(reason, FIELD_VCHAR | OWS) == reason.len() => reason,
+        _ => return None,
+    };
+    Some(StatusLine { http_version, status_code, reason })
+}
+
+/// Parses a header line: `field-name ":" OWS field-value OWS`.
+pub fn parse_header_field(line: &[u8]) -> Option<HeaderField<'_>> {
+    let name_len = span(line, TOKEN);
+    let rest = line[name_len..].strip_prefix(b":").filter(|_| name_len > 0)?;
+    let value = trim_ows(rest);
+    if span(value, FIELD_VCHAR | OWS) != value.len() {
+        return None;
+    }
+    Some(HeaderField { name: &line[..name_len], value })
+}
+
+/// Parses a chunk-size line, CRLF included, and returns its hex digits. Chunk extensions are
+/// accepted without being looked at, as long as they hold nothing a field value couldn't.
+pub fn parse_chunk_header(line: &[u8]) -> Option<&[u8]> {
+    let size_len = span(line, HEXDIG);
+    if size_len == 0 || size_len > 16 {
+        return None;
+    }
+    let mut rest = &line[size_len..];
+    // The extension may follow BWS, and holds spaces and tabs anywhere after its `;`
+    if let Some(extension) = rest[span(rest, OWS)..].strip_prefix(b";") {
+        rest = &extension[span(extension, FIELD_VCHAR | OWS)..];
+    }
+    (rest[span(rest, OWS)..] == *b"\r\n").then_some(&line[..size_len])
+}
+
+/// Checks a method or header name against the `token` grammar.
+pub fn is_token(bytes: &[u8]) -> bool {
+    all(bytes, TOKEN)
+}
+
+/// Checks a request target: one or more visible ASCII characters.
+pub fn is_request_target(bytes: &[u8]) -> bool {
+    all(bytes, VCHAR)
+}
+
+/// Checks a header value with no surrounding whitespace; the empty value is allowed.
+pub fn is_field_value(bytes: &[u8]) -> bool {
+    match (bytes.first(), bytes.last()) {
+        (Some(&first), Some(&last)) => {
+            CLASSES[first as usize] & FIELD_VCHAR != 0
+                && CLASSES[last as usize] & FIELD_VCHAR != 0
+                && span(bytes, FIELD_VCHAR | OWS) == bytes.len()
+        }
+        _ => true,
+    }
+}
+
+/// Checks a `Content-Length` value: one or more decimal digits.
+pub fn is_content_length(bytes: &[u8]) -> bool {
+    all(bytes, DIGIT)
+}
@@ -1,12 +1,14 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::error::Error;
 use lazy_static::lazy_static;
 
+use super::events::EventType;
+use super::util::{ErrorKind, ProtocolError};
+
 /// Enum representing various connection states and actions.
 ///
 /// Used to track connection roles, request/response flow, protocol switching, and connection closure.
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
 pub enum Sentinel {
     Client,
     Server,
@@ -23,44 +25,77 @@ pub enum Sentinel {
     SwitchConnect,
 }
 
-#[derive(Debug)]
-pub struct LocalProtocolError(String);
-
-impl std::fmt::Display for LocalProtocolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl Error for LocalProtocolError {}
-
-/// Type alias for an event callback function.
-type Event = Box<dyn Fn()>;
+/// Key of an event-triggered transition: the event type, plus an optional qualifier.
+///
+/// The qualifier is `Some(Sentinel::Client)` when the server's state machine sees the client's
+/// `Request`, and `Some(Sentinel::SwitchUpgrade)`/`Some(Sentinel::SwitchConnect)` when a server
+/// event accepts a protocol switch proposed by the client.
+type EventKey = (EventType, Option<Sentinel>);
 
 /// Type alias for a nested map representing state transitions for events.
-type EventTransitionType = HashMap<Sentinel, HashMap<Sentinel, HashMap<Sentinel, Sentinel>>>;
+type EventTransitionType = HashMap<Sentinel, HashMap<Sentinel, HashMap<EventKey, Sentinel>>>;
 
 lazy_static! {
     /// A pre-defined map for event-triggered state transitions.
-    /// 
-    /// This maps the current state and event to the resulting state for `Client` and `Server` roles.
+    ///
+    /// This maps the role, its current state and the event to the resulting state for `Client`
+    /// and `Server` roles. Anything missing from the map is an illegal event.
     static ref EVENT_TRIGGERED_TRANSITIONS: EventTransitionType = {
         let mut map = HashMap::new();
-        
+
         let mut client_map = HashMap::new();
         client_map.insert(Sentinel::Idle, HashMap::from([
-            (Sentinel::SwitchConnect, Sentinel::Closed),
-            (Sentinel::SwitchUpgrade, Sentinel::Closed),
+            ((EventType::Request, None), Sentinel::SendBody),
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
         ]));
+        client_map.insert(Sentinel::SendBody, HashMap::from([
+            ((EventType::Data, None), Sentinel::SendBody),
+            ((EventType::EndOfMessage, None), Sentinel::Done),
+        ]));
+        client_map.insert(Sentinel::Done, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+        ]));
+        client_map.insert(Sentinel::MustClose, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+        ]));
+        client_map.insert(Sentinel::Closed, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+        ]));
+        client_map.insert(Sentinel::MightSwitchProtocol, HashMap::new());
+        client_map.insert(Sentinel::SwitchedProtocol, HashMap::new());
+        client_map.insert(Sentinel::Error, HashMap::new());
         map.insert(Sentinel::Client, client_map);
 
         let mut server_map = HashMap::new();
         server_map.insert(Sentinel::Idle, HashMap::from([
-            (Sentinel::SwitchConnect, Sentinel::Closed),
-            (Sentinel::SwitchUpgrade, Sentinel::Closed),
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+            ((EventType::Response, None), Sentinel::SendBody),
+            // The server's state machine sees the client's request, in this form
+            ((EventType::Request, Some(Sentinel::Client)), Sentinel::SendResponse),
+        ]));
+        server_map.insert(Sentinel::SendResponse, HashMap::from([
+            ((EventType::InformationalResponse, None), Sentinel::SendResponse),
+            ((EventType::Response, None), Sentinel::SendBody),
+            ((EventType::InformationalResponse, Some(Sentinel::SwitchUpgrade)), Sentinel::SwitchedProtocol),
+            ((EventType::Response, Some(Sentinel::SwitchConnect)), Sentinel::SwitchedProtocol),
+        ]));
+        server_map.insert(Sentinel::SendBody, HashMap::from([
+            ((EventType::Data, None), Sentinel::SendBody),
+            ((EventType::EndOfMessage, None), Sentinel::Done),
+        ]));
+        server_map.insert(Sentinel::Done, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+        ]));
+        server_map.insert(Sentinel::MustClose, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
         ]));
+        server_map.insert(Sentinel::Closed, HashMap::from([
+            ((EventType::ConnectionClosed, None), Sentinel::Closed),
+        ]));
+        server_map.insert(Sentinel::SwitchedProtocol, HashMap::new());
+        server_map.insert(Sentinel::Error, HashMap::new());
         map.insert(Sentinel::Server, server_map);
-        
+
         map
     };
 }
@@ -70,19 +105,25 @@ type StateTransitionType = HashMap<(Sentinel, Sentinel), HashMap<Sentinel, Senti
 
 lazy_static! {
     /// A pre-defined map for state-triggered transitions.
-    /// 
-    /// This defines how the system transitions from one state to another based on specific state pairs.
+    ///
+    /// This maps a joint `(client state, server state)` to the states it forces on either role.
     static ref STATE_TRIGGERED_TRANSITIONS: StateTransitionType = {
         let mut map = HashMap::new();
-        
-        map.insert((Sentinel::Done, Sentinel::Closed), HashMap::from([(Sentinel::Server, Sentinel::MustClose)]));
+
+        // Protocol negotiation
+        map.insert((Sentinel::MightSwitchProtocol, Sentinel::SwitchedProtocol), HashMap::from([(Sentinel::Client, Sentinel::SwitchedProtocol)]));
+        // Socket shutdown
+        map.insert((Sentinel::Closed, Sentinel::Done), HashMap::from([(Sentinel::Server, Sentinel::MustClose)]));
+        map.insert((Sentinel::Closed, Sentinel::Idle), HashMap::from([(Sentinel::Server, Sentinel::MustClose)]));
+        map.insert((Sentinel::Error, Sentinel::Done), HashMap::from([(Sentinel::Server, Sentinel::MustClose)]));
+        map.insert((Sentinel::Done, Sentinel::Closed), HashMap::from([(Sentinel::Client, Sentinel::MustClose)]));
+        map.insert((Sentinel::Idle, Sentinel::Closed), HashMap::from([(Sentinel::Client, Sentinel::MustClose)]));
         map.insert((Sentinel::Done, Sentinel::Error), HashMap::from([(Sentinel::Client, Sentinel::MustClose)]));
-        
+
         map
     };
 }
 
-#[derive(Default)]
 /// Represents the state of a connection, including keep-alive status,
 /// pending switch proposals, and the states of the client and server.
 pub struct ConnectionState {
@@ -91,6 +132,12 @@ pub struct ConnectionState {
     pub states: HashMap<Sentinel, Sentinel>,
 }
 
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConnectionState {
     /// Creates a new `ConnectionState` with initial values.
     ///
@@ -99,7 +146,10 @@ impl ConnectionState {
     ///
     /// # Example
     /// ```
+    /// use yo::http1::state::ConnectionState;
+    ///
     /// let conn_state = ConnectionState::new();
+    /// assert!(conn_state.keep_alive);
     /// ```
     pub fn new() -> Self {
         ConnectionState {
@@ -112,14 +162,31 @@ impl ConnectionState {
         }
     }
 
+    /// Returns the current state of `role`.
+    ///
+    /// # Example
+    /// ```
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let conn_state = ConnectionState::new();
+    /// assert_eq!(conn_state.state(Sentinel::Server), Sentinel::Idle);
+    /// ```
+    pub fn state(&self, role: Sentinel) -> Sentinel {
+        self.states[&role]
+    }
+
     /// Processes an error by marking the given `role` as `Error` and triggering state transitions.
     ///
     /// # Example
     /// ```
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let mut conn_state = ConnectionState::new();
     /// conn_state.process_error(Sentinel::Client);
+    /// assert_eq!(conn_state.state(Sentinel::Client), Sentinel::Error);
     /// ```
     pub fn process_error(&mut self, role: Sentinel) {
-        self.states.insert(role, Sentinel::Error); // **Fix #1**: No need to clone the role.
+        self.states.insert(role, Sentinel::Error);
         self.fire_state_triggered_transitions();
     }
 
@@ -127,6 +194,9 @@ impl ConnectionState {
     ///
     /// # Example
     /// ```
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let mut conn_state = ConnectionState::new();
     /// conn_state.process_keep_alive_disabled();
     /// ```
     pub fn process_keep_alive_disabled(&mut self) {
@@ -138,6 +208,9 @@ impl ConnectionState {
     ///
     /// # Example
     /// ```
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let mut conn_state = ConnectionState::new();
     /// conn_state.process_client_switch_proposal(Sentinel::SwitchUpgrade);
     /// ```
     pub fn process_client_switch_proposal(&mut self, switch_event: Sentinel) {
@@ -145,127 +218,163 @@ impl ConnectionState {
         self.fire_state_triggered_transitions();
     }
 
-    /// Processes an event and triggers transitions based on the event type, including handling
-    /// server switch events.
+    /// Processes an event sent by `role` and triggers the resulting transitions.
+    ///
+    /// `server_switch_event` is set when a server event accepts one of the client's pending switch
+    /// proposals.
+    ///
+    /// # Errors
+    /// Returns an `IllegalEvent` error, and leaves the states untouched, if `role` can't send
+    /// `event_type` in its current state or accepts a switch that was never proposed.
     ///
     /// # Example
     /// ```
-    /// conn_state.process_event(Sentinel::Client, Sentinel::SwitchConnect, None);
+    /// use yo::http1::events::EventType;
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let mut conn_state = ConnectionState::new();
+    /// conn_state.process_event(Sentinel::Client, EventType::Request, None).unwrap();
+    /// assert_eq!(conn_state.state(Sentinel::Client), Sentinel::SendBody);
+    /// assert_eq!(conn_state.state(Sentinel::Server), Sentinel::SendResponse);
+    ///
+    /// // The client can't start a second request before finishing the first one
+    /// assert!(conn_state.process_event(Sentinel::Client, EventType::Request, None).is_err());
+    /// assert_eq!(conn_state.state(Sentinel::Client), Sentinel::SendBody);
     /// ```
-    pub fn process_event(&mut self, role: Sentinel, event_type: Sentinel, server_switch_event: Option<Sentinel>) {
-        let mut event_type = event_type; // **Fix #2**: No need to clone the event_type
-        
-        if let Some(server_switch_event) = server_switch_event {
-            assert_eq!(role, Sentinel::Server);
-            if !self.pending_switch_proposals.contains(&server_switch_event) {
-                panic!("Received server _SWITCH_UPGRADE event without a pending proposal");
+    pub fn process_event(
+        &mut self,
+        role: Sentinel,
+        event_type: EventType,
+        server_switch_event: Option<Sentinel>,
+    ) -> Result<(), ProtocolError> {
+        if let Some(switch_event) = server_switch_event {
+            if role != Sentinel::Server {
+                return Err(ProtocolError::local(
+                    ErrorKind::IllegalEvent,
+                    format!("only the server can accept a protocol switch, not {:?}", role),
+                ));
+            }
+            if !self.pending_switch_proposals.contains(&switch_event) {
+                return Err(ProtocolError::local(
+                    ErrorKind::IllegalEvent,
+                    format!("Received server {:?} event without a pending proposal", switch_event),
+                ));
             }
-            event_type = Sentinel::SwitchUpgrade; 
         }
 
-        self.fire_event_triggered_transitions(role, event_type);
-        /* 
-        if event_type == Sentinel::SwitchUpgrade {
-            assert_eq!(role, Sentinel::Client);
-            self.fire_event_triggered_transitions(Sentinel::Server, event_type);
+        let new_state = self.event_transition(role, (event_type, server_switch_event))?;
+        // The server's state machine sees the client's request too
+        let server_state = if event_type == EventType::Request {
+            if role != Sentinel::Client {
+                return Err(ProtocolError::local(ErrorKind::IllegalEvent, "only the client can send a Request"));
+            }
+            Some(self.event_transition(Sentinel::Server, (event_type, Some(Sentinel::Client)))?)
+        } else {
+            None
+        };
+
+        if server_switch_event.is_none() && event_type == EventType::Response {
+            self.pending_switch_proposals.clear();
+        }
+        self.states.insert(role, new_state);
+        if let Some(server_state) = server_state {
+            self.states.insert(Sentinel::Server, server_state);
         }
-        */
         self.fire_state_triggered_transitions();
+        Ok(())
     }
 
-    /// Handles transitions triggered by events, updating the state based on role and event type.
-    ///
-    /// # Panics
-    /// Panics if the event type cannot be handled for the current role and state.
-    fn fire_event_triggered_transitions(&mut self, role: Sentinel, event_type: Sentinel) {
-        // **Fix #4**: Avoid using `unwrap()` directly.
-        let state = match self.states.get(&role) {
-            Some(state) => state,
-            None => panic!("Role {:?} not found in states.", role),
-        };
-
-        if let Some(new_state) = EVENT_TRIGGERED_TRANSITIONS
+    /// Looks up the state `role` moves to on `event`, without applying it.
+    fn event_transition(&self, role: Sentinel, event: EventKey) -> Result<Sentinel, ProtocolError> {
+        let state = self.state(role);
+        EVENT_TRIGGERED_TRANSITIONS
             .get(&role)
-            .and_then(|role_map| role_map.get(state))
-            .and_then(|event_map| event_map.get(&event_type))
-        {
-            self.states.insert(role, new_state.clone());
-        } else {
-            // Placeholder for error handling instead of panicking
-            println!(
-                "Warning: can't handle event type {:?} when role={:?} and state={:?}",
-                event_type, role, state
-            );
-        }
+            .and_then(|role_map| role_map.get(&state))
+            .and_then(|event_map| event_map.get(&event))
+            .copied()
+            .ok_or_else(|| {
+                ProtocolError::local(
+                    ErrorKind::IllegalEvent,
+                    format!(
+                        "can't handle event type {:?} when role={:?} and state={:?}",
+                        event.0, role, state
+                    ),
+                )
+            })
     }
 
     /// Triggers state transitions that are based on the current states of the client and server,
-    /// and updates the states accordingly.
+    /// and updates the states accordingly until nothing changes any more.
     fn fire_state_triggered_transitions(&mut self) {
-        // **Fix #5**: Simplify the logic, no need for a loop.
-        let start_states = self.states.clone();
-    
-        // Handle pending switch proposals and client state transitions
-        if !self.pending_switch_proposals.is_empty() {
-            if self.states.get(&Sentinel::Client) == Some(&Sentinel::Done) {
+        loop {
+            let start_states = self.states.clone();
+
+            // A client that proposed a switch waits for the server's answer before it's done
+            if !self.pending_switch_proposals.is_empty()
+                && self.state(Sentinel::Client) == Sentinel::Done
+            {
                 self.states.insert(Sentinel::Client, Sentinel::MightSwitchProtocol);
             }
-        }
-    
-        if self.pending_switch_proposals.is_empty() {
-            if self.states.get(&Sentinel::Client) == Some(&Sentinel::MightSwitchProtocol) {
+
+            if self.pending_switch_proposals.is_empty()
+                && self.state(Sentinel::Client) == Sentinel::MightSwitchProtocol
+            {
                 self.states.insert(Sentinel::Client, Sentinel::Done);
             }
-        }
-    
-        // Handle connection closure when keep-alive is disabled
-        if !self.keep_alive {
-            for role in [Sentinel::Client, Sentinel::Server].iter() {
-                if self.states.get(role) == Some(&Sentinel::Done) {
-                    self.states.insert(role.clone(), Sentinel::MustClose);
+
+            // Handle connection closure when keep-alive is disabled
+            if !self.keep_alive {
+                for role in [Sentinel::Client, Sentinel::Server] {
+                    if self.state(role) == Sentinel::Done {
+                        self.states.insert(role, Sentinel::MustClose);
+                    }
                 }
             }
-        }
-    
-        // Check for state-triggered transitions
-        let joint_state = (
-            self.states.get(&Sentinel::Client).unwrap_or(&Sentinel::Idle),
-            self.states.get(&Sentinel::Server).unwrap_or(&Sentinel::Idle),
-        );
-        /* 
-        if let Some(changes) = STATE_TRIGGERED_TRANSITIONS.get(&joint_state) {
-            for (role, new_state) in changes {
-                self.states.insert(role.clone(), new_state.clone());
+
+            let joint_state = (self.state(Sentinel::Client), self.state(Sentinel::Server));
+            if let Some(changes) = STATE_TRIGGERED_TRANSITIONS.get(&joint_state) {
+                self.states.extend(changes);
+            }
+
+            if self.states == start_states {
+                return;
             }
-        }
-        */
-        // Only return if no changes were made to the states
-        if self.states == start_states {
-            return;
         }
     }
+
     /// Starts a new cycle by resetting the connection states to `Idle` for both client and server.
     ///
-    /// # Panics
-    /// Panics if the current state is not `Done` for both client and server.
+    /// # Errors
+    /// Returns an `IllegalEvent` error if the current state is not `Done` for both client and
+    /// server.
     ///
     /// # Example
     /// ```
-    /// conn_state.start_next_cycle();
+    /// use yo::http1::state::{ConnectionState, Sentinel};
+    ///
+    /// let mut conn_state = ConnectionState::new();
+    /// assert!(conn_state.start_next_cycle().is_err());
+    ///
+    /// conn_state.states.insert(Sentinel::Client, Sentinel::Done);
+    /// conn_state.states.insert(Sentinel::Server, Sentinel::Done);
+    /// conn_state.start_next_cycle().unwrap();
+    /// assert_eq!(conn_state.states[&Sentinel::Client], Sentinel::Idle);
     /// ```
-    pub fn start_next_cycle(&mut self) {
-        if self.states != HashMap::from([
-            (Sentinel::Client, Sentinel::Done),
-            (Sentinel::Server, Sentinel::Done),
-        ]) {
-            panic!("not in a reusable state. self.states={:?}", self.states);
+    pub fn start_next_cycle(&mut self) -> Result<(), ProtocolError> {
+        if self.state(Sentinel::Client) != Sentinel::Done || self.state(Sentinel::Server) != Sentinel::Done {
+            return Err(ProtocolError::local(
+                ErrorKind::IllegalEvent,
+                format!("not in a reusable state. self.states={:?}", self.states),
+            ));
         }
 
-        assert!(self.keep_alive);
-        assert!(self.pending_switch_proposals.is_empty());
+        // Done on both sides is only reachable with keep-alive on and no pending switch
+        debug_assert!(self.keep_alive);
+        debug_assert!(self.pending_switch_proposals.is_empty());
         self.states = HashMap::from([
             (Sentinel::Client, Sentinel::Idle),
             (Sentinel::Server, Sentinel::Idle),
         ]);
+        Ok(())
     }
 }
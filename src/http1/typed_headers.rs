@@ -0,0 +1,1099 @@
+//! Typed parsers and formatters for the header fields applications handle most.
+//!
+//! Date-valued fields (`Date`, `Last-Modified`, `Expires`, `Retry-After`) go through the
+//! [`super::date`] module.
+//!
+//! Each type here implements [`TypedHeader`]. It parses from the values of every field with its
+//! name, in wire order, and formats back into a single field value that parses to the same
+//! thing again. `Headers::typed` and `Headers::set_typed` do the lookup and the replacement.
+//! Parse errors are `BadHeader` errors whose message names the header.
+//!
+//! # Example
+//!
+//! ```rust
+//! use yo::http1::headers::Headers;
+//! use yo::http1::typed_headers::{CacheControl, ContentType, Host};
+//!
+//! let mut headers = Headers::normalize_and_validate(&[
+//!     (b"Host".to_vec(), b"example.com:8080".to_vec()),
+//!     (b"Content-Type".to_vec(), b"text/HTML; Charset=\"utf-8\"".to_vec()),
+//! ], false).unwrap();
+//!
+//! let host: Host = headers.typed().unwrap().unwrap();
+//! assert_eq!((&host.host[..], host.port), (&b"example.com"[..], Some(8080)));
+//! let content_type: ContentType = headers.typed().unwrap().unwrap();
+//! assert_eq!(content_type.media_type, b"text/html");
+//! assert_eq!(content_type.charset(), Some(&b"utf-8"[..]));
+//! assert!(headers.typed::<CacheControl>().unwrap().is_none());
+//!
+//! headers.set_typed(&CacheControl::default().with(b"max-age", Some(b"60"))).unwrap();
+//! assert_eq!(headers.get(b"cache-control"), Some(&b"max-age=60"[..]));
+//!
+//! let err = Headers::normalize_and_validate(&[(b"Host".to_vec(), b"example.com:http".to_vec())], false)
+//!     .unwrap()
+//!     .typed::<Host>()
+//!     .unwrap_err();
+//! assert!(err.message().contains("Host"));
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use super::date::{format_http_date, parse_http_date_at};
+use super::grammar;
+use super::headers::trim_ows;
+use super::util::{ErrorKind, ProtocolError};
+
+/// A header field with a typed representation.
+pub trait TypedHeader: Sized {
+    /// The header name, in the casing `Headers::set_typed` sends it with.
+    const NAME: &'static [u8];
+
+    /// Parses the header from the values of every field called `NAME`, in wire order.
+    ///
+    /// # Returns
+    /// The typed header, or a `BadHeader` error naming the header.
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError>;
+
+    /// Formats the header as a single field value.
+    fn format(&self) -> Vec<u8>;
+}
+
+/// A `name=value` parameter, as found after the media type in `Content-Type` and `Accept`.
+/// Names are lower-cased; values are kept as sent, with any quoting undone.
+pub type Param = (Vec<u8>, Vec<u8>);
+
+/// `Content-Type`: a media type and its parameters.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{ContentType, TypedHeader};
+///
+/// let content_type = ContentType::new("multipart/form-data").with_param(b"boundary", b"a b");
+/// assert_eq!(content_type.format(), b"multipart/form-data; boundary=\"a b\"");
+/// assert_eq!(ContentType::parse(&[&content_type.format()]).unwrap(), content_type);
+///
+/// assert!(ContentType::parse(&[b"text"]).is_err());
+/// assert!(ContentType::parse(&[b"text/html", b"text/plain"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ContentType {
+    /// The lower-cased `type/subtype`, e.g. `text/html`.
+    pub media_type: Vec<u8>,
+    pub params: Vec<Param>,
+}
+
+impl ContentType {
+    /// Creates a `ContentType` without parameters.
+    pub fn new(media_type: impl Into<Vec<u8>>) -> Self {
+        ContentType { media_type: media_type.into().to_ascii_lowercase(), params: Vec::new() }
+    }
+    /// Adds a parameter.
+    pub fn with_param(mut self, name: &[u8], value: &[u8]) -> Self {
+        self.params.push((name.to_ascii_lowercase(), value.to_vec()));
+        self
+    }
+    /// Returns the value of a parameter, ignoring the case of `name`.
+    pub fn param(&self, name: &[u8]) -> Option<&[u8]> {
+        find_param(&self.params, name)
+    }
+    /// Returns the `charset` parameter.
+    pub fn charset(&self) -> Option<&[u8]> {
+        self.param(b"charset")
+    }
+}
+
+impl TypedHeader for ContentType {
+    const NAME: &'static [u8] = b"Content-Type";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        let parts = split_unquoted(single(Self::NAME, values)?, b';');
+        let media_type = parse_media_type(parts[0]).ok_or_else(|| invalid(Self::NAME, "bad media type"))?;
+        let params = parse_params(&parts[1..]).ok_or_else(|| invalid(Self::NAME, "bad parameter"))?;
+        Ok(ContentType { media_type, params })
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = self.media_type.clone();
+        format_params(&self.params, &mut out);
+        out
+    }
+}
+
+/// `Content-Length`: the length of the body in bytes.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{ContentLength, TypedHeader};
+///
+/// assert_eq!(ContentLength::parse(&[b"42"]).unwrap(), ContentLength(42));
+/// assert_eq!(ContentLength(42).format(), b"42");
+/// assert!(ContentLength::parse(&[b"99999999999999999999999"]).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLength(pub u64);
+
+impl TypedHeader for ContentLength {
+    const NAME: &'static [u8] = b"Content-Length";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        let value = single(Self::NAME, values)?;
+        if !grammar::is_content_length(value) {
+            return Err(invalid(Self::NAME, "not a decimal number"));
+        }
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(ContentLength)
+            .ok_or_else(|| invalid(Self::NAME, "too large"))
+    }
+
+    fn format(&self) -> Vec<u8> {
+        self.0.to_string().into_bytes()
+    }
+}
+
+/// `Connection`: the connection options, lower-cased.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{Connection, TypedHeader};
+///
+/// let connection = Connection::parse(&[b"Keep-Alive, Upgrade", b"close"]).unwrap();
+/// assert!(connection.contains(b"upgrade") && connection.contains(b"CLOSE"));
+/// assert_eq!(connection.format(), b"keep-alive, upgrade, close");
+/// assert!(Connection::parse(&[b"close;now"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Connection(pub Vec<Vec<u8>>);
+
+impl Connection {
+    /// Checks whether an option is listed, ignoring case.
+    pub fn contains(&self, option: &[u8]) -> bool {
+        self.0.iter().any(|listed| listed.eq_ignore_ascii_case(option))
+    }
+}
+
+impl TypedHeader for Connection {
+    const NAME: &'static [u8] = b"Connection";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        parse_token_list(Self::NAME, values).map(Connection)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        self.0.join(&b", "[..])
+    }
+}
+
+/// One media range of an `Accept` header, with its weight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRange {
+    /// The lower-cased `type/subtype`, either of which may be `*`.
+    pub media_type: Vec<u8>,
+    /// The parameters other than the weight.
+    pub params: Vec<Param>,
+    /// The weight in thousandths, from 0 to 1000; 1000 if the range has no `q`.
+    pub quality: u16,
+}
+
+/// `Accept`: the media ranges a client takes, with their q-values.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{Accept, TypedHeader};
+///
+/// let accept = Accept::parse(&[b"text/*;q=0.3, text/html;level=1, */*;q=0"]).unwrap();
+/// assert_eq!(accept.0[0].quality, 300);
+/// assert_eq!(accept.0[1].params, vec![(b"level".to_vec(), b"1".to_vec())]);
+/// let preferred: Vec<&[u8]> = accept.by_preference().iter().map(|range| &range.media_type[..]).collect();
+/// assert_eq!(preferred, vec![&b"text/html"[..], b"text/*", b"*/*"]);
+///
+/// assert_eq!(accept.format(), b"text/*; q=0.3, text/html; level=1, */*; q=0");
+/// assert_eq!(Accept::parse(&[&accept.format()]).unwrap(), accept);
+///
+/// assert!(Accept::parse(&[b"text/html;q=1.5"]).is_err());
+/// assert!(Accept::parse(&[b"text/html;q=0.1234"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Accept(pub Vec<MediaRange>);
+
+impl Accept {
+    /// Returns the media ranges from most to least preferred, keeping the order they were
+    /// listed in among ranges of equal weight. Ranges with a weight of 0 are not acceptable,
+    /// but are still returned, last.
+    pub fn by_preference(&self) -> Vec<&MediaRange> {
+        let mut ranges: Vec<&MediaRange> = self.0.iter().collect();
+        ranges.sort_by_key(|range| std::cmp::Reverse(range.quality));
+        ranges
+    }
+}
+
+impl TypedHeader for Accept {
+    const NAME: &'static [u8] = b"Accept";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        list_elements(values)
+            .into_iter()
+            .map(|element| {
+                let parts = split_unquoted(element, b';');
+                let media_type = parse_media_type(parts[0]).ok_or_else(|| invalid(Self::NAME, "bad media range"))?;
+                let mut params = parse_params(&parts[1..]).ok_or_else(|| invalid(Self::NAME, "bad parameter"))?;
+                let mut quality = 1000;
+                if let Some(at) = params.iter().position(|(name, _)| name == b"q") {
+                    let (_, weight) = params.remove(at);
+                    quality = parse_qvalue(&weight).ok_or_else(|| invalid(Self::NAME, "bad q-value"))?;
+                }
+                Ok(MediaRange { media_type, params, quality })
+            })
+            .collect::<Result<_, _>>()
+            .map(Accept)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.extend_from_slice(b", ");
+            }
+            out.extend_from_slice(&range.media_type);
+            format_params(&range.params, &mut out);
+            if range.quality != 1000 {
+                out.extend_from_slice(b"; q=");
+                format_qvalue(range.quality, &mut out);
+            }
+        }
+        out
+    }
+}
+
+/// One `Cache-Control` directive: a lower-cased name and an optional argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDirective {
+    pub name: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// `Cache-Control`: the cache directives.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{CacheControl, TypedHeader};
+///
+/// let cache_control = CacheControl::parse(&[b"No-Cache=\"Set-Cookie, Vary\", max-age=3600", b"private"]).unwrap();
+/// assert_eq!(cache_control.max_age(), Some(3600));
+/// assert_eq!(cache_control.value(b"no-cache"), Some(&b"Set-Cookie, Vary"[..]));
+/// assert!(cache_control.contains(b"private"));
+///
+/// assert_eq!(cache_control.format(), b"no-cache=\"Set-Cookie, Vary\", max-age=3600, private");
+/// assert_eq!(CacheControl::parse(&[&cache_control.format()]).unwrap(), cache_control);
+/// assert!(CacheControl::parse(&[b"max-age=\"60"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CacheControl(pub Vec<CacheDirective>);
+
+impl CacheControl {
+    /// Adds a directive.
+    pub fn with(mut self, name: &[u8], value: Option<&[u8]>) -> Self {
+        self.0.push(CacheDirective { name: name.to_ascii_lowercase(), value: value.map(<[u8]>::to_vec) });
+        self
+    }
+    /// Checks whether a directive is present, ignoring the case of `name`.
+    pub fn contains(&self, name: &[u8]) -> bool {
+        self.directive(name).is_some()
+    }
+    /// Returns the argument of a directive, if it is present and has one.
+    pub fn value(&self, name: &[u8]) -> Option<&[u8]> {
+        self.directive(name)?.value.as_deref()
+    }
+    /// Returns the `max-age` directive in seconds, if it is present and a number.
+    pub fn max_age(&self) -> Option<u64> {
+        std::str::from_utf8(self.value(b"max-age")?).ok()?.parse().ok()
+    }
+    fn directive(&self, name: &[u8]) -> Option<&CacheDirective> {
+        self.0.iter().find(|directive| directive.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl TypedHeader for CacheControl {
+    const NAME: &'static [u8] = b"Cache-Control";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        list_elements(values)
+            .into_iter()
+            .map(|element| {
+                let (name, value) = match element.iter().position(|&b| b == b'=') {
+                    Some(eq) => (trim_ows(&element[..eq]), Some(trim_ows(&element[eq + 1..]))),
+                    None => (element, None),
+                };
+                if !grammar::is_token(name) {
+                    return Err(invalid(Self::NAME, "bad directive name"));
+                }
+                let value = match value {
+                    Some(value) => Some(parse_word(value).ok_or_else(|| invalid(Self::NAME, "bad directive argument"))?),
+                    None => None,
+                };
+                Ok(CacheDirective { name: name.to_ascii_lowercase(), value })
+            })
+            .collect::<Result<_, _>>()
+            .map(CacheControl)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, directive) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.extend_from_slice(b", ");
+            }
+            out.extend_from_slice(&directive.name);
+            if let Some(value) = &directive.value {
+                out.push(b'=');
+                format_word(value, &mut out);
+            }
+        }
+        out
+    }
+}
+
+/// An entity tag: an opaque validator, strong unless marked weak.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::EntityTag;
+///
+/// let strong = EntityTag::strong(b"v1");
+/// let weak = EntityTag::weak(b"v1");
+/// assert!(strong.weak_eq(&weak) && !strong.strong_eq(&weak));
+/// assert!(strong.strong_eq(&EntityTag::strong(b"v1")));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub weak: bool,
+    /// The tag without its quotes.
+    pub tag: Vec<u8>,
+}
+
+impl EntityTag {
+    /// Creates a strong entity tag.
+    pub fn strong(tag: &[u8]) -> Self {
+        EntityTag { weak: false, tag: tag.to_vec() }
+    }
+    /// Creates a weak entity tag.
+    pub fn weak(tag: &[u8]) -> Self {
+        EntityTag { weak: true, tag: tag.to_vec() }
+    }
+    /// Strong comparison, as `If-Match` uses: both tags are strong and equal.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+    /// Weak comparison, as `If-None-Match` uses: the tags are equal, weak or not.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+    fn format_into(&self, out: &mut Vec<u8>) {
+        if self.weak {
+            out.extend_from_slice(b"W/");
+        }
+        out.push(b'"');
+        out.extend_from_slice(&self.tag);
+        out.push(b'"');
+    }
+}
+
+/// `ETag`: the entity tag of the representation being sent.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{ETag, EntityTag, TypedHeader};
+///
+/// assert_eq!(ETag::parse(&[b"W/\"xyzzy\""]).unwrap(), ETag(EntityTag::weak(b"xyzzy")));
+/// assert_eq!(ETag(EntityTag::strong(b"xyzzy")).format(), b"\"xyzzy\"");
+/// assert!(ETag::parse(&[b"xyzzy"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(pub EntityTag);
+
+impl TypedHeader for ETag {
+    const NAME: &'static [u8] = b"ETag";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        match parse_entity_tags(single(Self::NAME, values)?) {
+            Some(EntityTags::Tags(mut tags)) if tags.len() == 1 => Ok(ETag(tags.remove(0))),
+            _ => Err(invalid(Self::NAME, "not a single entity tag")),
+        }
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.0.format_into(&mut out);
+        out
+    }
+}
+
+/// The value of `If-Match` and `If-None-Match`: `*`, or a list of entity tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityTags {
+    Any,
+    Tags(Vec<EntityTag>),
+}
+
+impl EntityTags {
+    fn format(&self) -> Vec<u8> {
+        match self {
+            EntityTags::Any => b"*".to_vec(),
+            EntityTags::Tags(tags) => {
+                let mut out = Vec::new();
+                for (i, tag) in tags.iter().enumerate() {
+                    if i > 0 {
+                        out.extend_from_slice(b", ");
+                    }
+                    tag.format_into(&mut out);
+                }
+                out
+            }
+        }
+    }
+}
+
+/// `If-Match`: the request applies only if the current entity tag strongly matches one of these.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{EntityTag, EntityTags, IfMatch, TypedHeader};
+///
+/// let if_match = IfMatch::parse(&[b"\"a,b\", W/\"c\"", b"\"d\""]).unwrap();
+/// assert!(if_match.matches(&EntityTag::strong(b"a,b")));
+/// assert!(!if_match.matches(&EntityTag::strong(b"c")));
+/// assert_eq!(if_match.format(), b"\"a,b\", W/\"c\", \"d\"");
+///
+/// assert_eq!(IfMatch::parse(&[b"*"]).unwrap(), IfMatch(EntityTags::Any));
+/// assert!(IfMatch::parse(&[b"*, \"a\""]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfMatch(pub EntityTags);
+
+impl IfMatch {
+    /// Checks the current entity tag of the target resource against the condition.
+    pub fn matches(&self, current: &EntityTag) -> bool {
+        match &self.0 {
+            EntityTags::Any => true,
+            EntityTags::Tags(tags) => tags.iter().any(|tag| tag.strong_eq(current)),
+        }
+    }
+}
+
+impl TypedHeader for IfMatch {
+    const NAME: &'static [u8] = b"If-Match";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        parse_entity_tag_fields(Self::NAME, values).map(IfMatch)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        self.0.format()
+    }
+}
+
+/// `If-None-Match`: the request applies only if the current entity tag weakly matches none of
+/// these.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{EntityTag, IfNoneMatch, TypedHeader};
+///
+/// let if_none_match = IfNoneMatch::parse(&[b"W/\"v1\""]).unwrap();
+/// assert!(if_none_match.matches(&EntityTag::strong(b"v1")));
+/// assert_eq!(IfNoneMatch::parse(&[&if_none_match.format()]).unwrap(), if_none_match);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfNoneMatch(pub EntityTags);
+
+impl IfNoneMatch {
+    /// Checks whether the current entity tag of the target resource is one of the listed ones,
+    /// in which case the request doesn't apply.
+    pub fn matches(&self, current: &EntityTag) -> bool {
+        match &self.0 {
+            EntityTags::Any => true,
+            EntityTags::Tags(tags) => tags.iter().any(|tag| tag.weak_eq(current)),
+        }
+    }
+}
+
+impl TypedHeader for IfNoneMatch {
+    const NAME: &'static [u8] = b"If-None-Match";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        parse_entity_tag_fields(Self::NAME, values).map(IfNoneMatch)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        self.0.format()
+    }
+}
+
+/// `Host`: the host and optional port of the target.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{Host, TypedHeader};
+///
+/// let host = Host::parse(&[b"[::1]:8443"]).unwrap();
+/// assert_eq!((&host.host[..], host.port), (&b"[::1]"[..], Some(8443)));
+/// assert_eq!(host.format(), b"[::1]:8443");
+/// assert_eq!(Host::parse(&[b"example.com"]).unwrap(), Host::new(b"example.com", None));
+///
+/// assert!(Host::parse(&[b"example.com:65536"]).is_err());
+/// assert!(Host::parse(&[b"exa mple.com"]).is_err());
+/// assert!(Host::parse(&[b"a:b:80"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// A registered name or IPv4 address, or an IPv6 address in brackets.
+    pub host: Vec<u8>,
+    pub port: Option<u16>,
+}
+
+impl Host {
+    /// Creates a `Host`.
+    pub fn new(host: &[u8], port: Option<u16>) -> Self {
+        Host { host: host.to_vec(), port }
+    }
+}
+
+impl TypedHeader for Host {
+    const NAME: &'static [u8] = b"Host";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        let value = single(Self::NAME, values)?;
+        let split_at = if value.starts_with(b"[") {
+            value.iter().position(|&b| b == b']').map(|end| end + 1)
+        } else {
+            value.iter().rposition(|&b| b == b':').or(Some(value.len()))
+        };
+        let (host, port) = value.split_at(split_at.ok_or_else(|| invalid(Self::NAME, "unterminated IPv6 address"))?);
+
+        let valid_host = match host.strip_prefix(b"[").and_then(|host| host.strip_suffix(b"]")) {
+            Some(address) => !address.is_empty() && address.iter().all(|&b| b.is_ascii_hexdigit() || b == b':' || b == b'.'),
+            None => host.iter().all(|&b| b.is_ascii_alphanumeric() || b"-._~%!$&'()*+,;=".contains(&b)),
+        };
+        if !valid_host {
+            return Err(invalid(Self::NAME, "bad host"));
+        }
+        let port = match port {
+            [] | [b':'] => None,
+            [b':', digits @ ..] if digits.iter().all(u8::is_ascii_digit) => Some(
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| digits.parse().ok())
+                    .ok_or_else(|| invalid(Self::NAME, "port out of range"))?,
+            ),
+            _ => return Err(invalid(Self::NAME, "bad port")),
+        };
+        Ok(Host { host: host.to_vec(), port })
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = self.host.clone();
+        if let Some(port) = self.port {
+            out.push(b':');
+            out.extend_from_slice(port.to_string().as_bytes());
+        }
+        out
+    }
+}
+
+/// One protocol offered in an `Upgrade` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Protocol {
+    pub name: Vec<u8>,
+    pub version: Option<Vec<u8>>,
+}
+
+/// `Upgrade`: the protocols a client would rather switch to, in order of preference.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{Protocol, TypedHeader, Upgrade};
+///
+/// let upgrade = Upgrade::parse(&[b"HTTP/2.0, websocket"]).unwrap();
+/// assert_eq!(upgrade.0[0], Protocol { name: b"HTTP".to_vec(), version: Some(b"2.0".to_vec()) });
+/// assert!(upgrade.contains(b"WebSocket"));
+/// assert_eq!(upgrade.format(), b"HTTP/2.0, websocket");
+/// assert!(Upgrade::parse(&[b"h2c/"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Upgrade(pub Vec<Protocol>);
+
+impl Upgrade {
+    /// Checks whether a protocol is offered, in any version, ignoring case.
+    pub fn contains(&self, name: &[u8]) -> bool {
+        self.0.iter().any(|protocol| protocol.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl TypedHeader for Upgrade {
+    const NAME: &'static [u8] = b"Upgrade";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        list_elements(values)
+            .into_iter()
+            .map(|element| {
+                let mut parts = element.splitn(2, |&b| b == b'/');
+                let name = parts.next().unwrap_or_default();
+                let version = parts.next();
+                if !grammar::is_token(name) || version.is_some_and(|version| !grammar::is_token(version)) {
+                    return Err(invalid(Self::NAME, "bad protocol"));
+                }
+                Ok(Protocol { name: name.to_vec(), version: version.map(<[u8]>::to_vec) })
+            })
+            .collect::<Result<_, _>>()
+            .map(Upgrade)
+    }
+
+    fn format(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, protocol) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.extend_from_slice(b", ");
+            }
+            out.extend_from_slice(&protocol.name);
+            if let Some(version) = &protocol.version {
+                out.push(b'/');
+                out.extend_from_slice(version);
+            }
+        }
+        out
+    }
+}
+
+/// `Vary`: the request headers a response depends on, lower-cased, or `*` for anything.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{TypedHeader, Vary};
+///
+/// let vary = Vary::parse(&[b"Accept-Encoding", b"Origin"]).unwrap();
+/// assert_eq!(vary, Vary::Fields(vec![b"accept-encoding".to_vec(), b"origin".to_vec()]));
+/// assert_eq!(vary.format(), b"accept-encoding, origin");
+/// assert_eq!(Vary::parse(&[b"*"]).unwrap(), Vary::Any);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vary {
+    Any,
+    Fields(Vec<Vec<u8>>),
+}
+
+impl TypedHeader for Vary {
+    const NAME: &'static [u8] = b"Vary";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        let fields = parse_token_list(Self::NAME, values)?;
+        if fields.iter().any(|field| field == b"*") {
+            return Ok(Vary::Any);
+        }
+        Ok(Vary::Fields(fields))
+    }
+
+    fn format(&self) -> Vec<u8> {
+        match self {
+            Vary::Any => b"*".to_vec(),
+            Vary::Fields(fields) => fields.join(&b", "[..]),
+        }
+    }
+}
+
+/// `Date`: when the message was generated.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use yo::http1::typed_headers::{Date, TypedHeader};
+///
+/// let date = Date::parse(&[b"Sun, 06 Nov 1994 08:49:37 GMT"]).unwrap();
+/// assert_eq!(date, Date(UNIX_EPOCH + Duration::from_secs(784111777)));
+/// assert_eq!(date.format(), b"Sun, 06 Nov 1994 08:49:37 GMT");
+/// assert!(Date::parse(&[b"yesterday"]).unwrap_err().message().contains("Date"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date(pub SystemTime);
+
+impl Date {
+    /// Parses the header like `parse`, placing a two-digit RFC 850 year relative to `now`
+    /// rather than the system clock.
+    pub fn parse_at(values: &[&[u8]], now: SystemTime) -> Result<Self, ProtocolError> {
+        parse_date_field(Self::NAME, values, now).map(Date)
+    }
+}
+
+impl TypedHeader for Date {
+    const NAME: &'static [u8] = b"Date";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        Self::parse_at(values, SystemTime::now())
+    }
+
+    fn format(&self) -> Vec<u8> {
+        format_http_date(self.0)
+    }
+}
+
+/// `Last-Modified`: when the representation last changed.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{LastModified, TypedHeader};
+///
+/// let last_modified = LastModified::parse(&[b"Sun, 06 Nov 1994 08:49:37 GMT"]).unwrap();
+/// assert_eq!(last_modified.format(), b"Sun, 06 Nov 1994 08:49:37 GMT");
+///
+/// // The century of an RFC 850 date depends on when it is read
+/// let now = last_modified.0;
+/// let rfc850 = LastModified::parse_at(&[b"Sunday, 06-Nov-94 08:49:37 GMT"], now).unwrap();
+/// assert_eq!(rfc850, last_modified);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastModified(pub SystemTime);
+
+impl LastModified {
+    /// Parses the header like `parse`, placing a two-digit RFC 850 year relative to `now`
+    /// rather than the system clock.
+    pub fn parse_at(values: &[&[u8]], now: SystemTime) -> Result<Self, ProtocolError> {
+        parse_date_field(Self::NAME, values, now).map(LastModified)
+    }
+}
+
+impl TypedHeader for LastModified {
+    const NAME: &'static [u8] = b"Last-Modified";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        Self::parse_at(values, SystemTime::now())
+    }
+
+    fn format(&self) -> Vec<u8> {
+        format_http_date(self.0)
+    }
+}
+
+/// `Expires`: when the response goes stale.
+///
+/// A cache has to treat an `Expires` that doesn't parse, such as `0`, as already in the past;
+/// that is up to the caller, since it only gets the parse error here.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::typed_headers::{Expires, TypedHeader};
+///
+/// let expires = Expires::parse(&[b"Sun Nov  6 08:49:37 1994"]).unwrap();
+/// assert_eq!(expires.format(), b"Sun, 06 Nov 1994 08:49:37 GMT");
+/// assert!(Expires::parse(&[b"0"]).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expires(pub SystemTime);
+
+impl Expires {
+    /// Parses the header like `parse`, placing a two-digit RFC 850 year relative to `now`
+    /// rather than the system clock.
+    pub fn parse_at(values: &[&[u8]], now: SystemTime) -> Result<Self, ProtocolError> {
+        parse_date_field(Self::NAME, values, now).map(Expires)
+    }
+}
+
+impl TypedHeader for Expires {
+    const NAME: &'static [u8] = b"Expires";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        Self::parse_at(values, SystemTime::now())
+    }
+
+    fn format(&self) -> Vec<u8> {
+        format_http_date(self.0)
+    }
+}
+
+/// `Retry-After`: when to try again, as a date or as a delay in seconds.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use yo::http1::typed_headers::{RetryAfter, TypedHeader};
+///
+/// assert_eq!(RetryAfter::parse(&[b"120"]).unwrap(), RetryAfter::Delay(Duration::from_secs(120)));
+/// assert!(matches!(RetryAfter::parse(&[b"Fri, 31 Dec 1999 23:59:59 GMT"]).unwrap(), RetryAfter::At(_)));
+/// assert_eq!(RetryAfter::Delay(Duration::from_millis(1500)).format(), b"1");
+/// assert!(RetryAfter::parse(&[b"-1"]).is_err());
+///
+/// let now = std::time::UNIX_EPOCH + Duration::from_secs(4_070_908_800); // 2099-01-01
+/// let at = RetryAfter::parse_at(&[b"Thursday, 01-Jan-05 00:00:00 GMT"], now).unwrap();
+/// assert!(matches!(at, RetryAfter::At(time) if time > now));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAfter {
+    At(SystemTime),
+    /// A delay counted from when the response was received, in whole seconds.
+    Delay(Duration),
+}
+
+impl RetryAfter {
+    /// Parses the header like `parse`, placing a two-digit RFC 850 year relative to `now`
+    /// rather than the system clock.
+    pub fn parse_at(values: &[&[u8]], now: SystemTime) -> Result<Self, ProtocolError> {
+        let value = single(Self::NAME, values)?;
+        if !value.is_empty() && value.iter().all(u8::is_ascii_digit) {
+            return std::str::from_utf8(value)
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .map(|seconds| RetryAfter::Delay(Duration::from_secs(seconds)))
+                .ok_or_else(|| invalid(Self::NAME, "delay too large"));
+        }
+        parse_date_field(Self::NAME, values, now).map(RetryAfter::At)
+    }
+}
+
+impl TypedHeader for RetryAfter {
+    const NAME: &'static [u8] = b"Retry-After";
+
+    fn parse(values: &[&[u8]]) -> Result<Self, ProtocolError> {
+        Self::parse_at(values, SystemTime::now())
+    }
+
+    fn format(&self) -> Vec<u8> {
+        match self {
+            RetryAfter::At(time) => format_http_date(*time),
+            RetryAfter::Delay(delay) => delay.as_secs().to_string().into_bytes(),
+        }
+    }
+}
+
+/// Builds the error for a header that doesn't parse, naming the header.
+fn invalid(name: &[u8], detail: &str) -> ProtocolError {
+    ProtocolError::local(
+        ErrorKind::BadHeader,
+        format!("invalid {} header: {}", String::from_utf8_lossy(name), detail),
+    )
+}
+
+/// Returns the value of a header that can only appear once.
+fn single<'a>(name: &[u8], values: &[&'a [u8]]) -> Result<&'a [u8], ProtocolError> {
+    match values {
+        [value] => Ok(value),
+        _ => Err(invalid(name, "more than one field")),
+    }
+}
+
+/// Splits `value` on `separator` wherever it isn't inside a quoted-string, trimming whitespace
+/// from every part. There is always at least one part.
+fn split_unquoted(value: &[u8], separator: u8) -> Vec<&[u8]> {
+    let mut parts = Vec::new();
+    let (mut start, mut in_quotes, mut escaped) = (0, false, false);
+    for (i, &b) in value.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if in_quotes && b == b'\\' {
+            escaped = true;
+        } else if b == b'"' {
+            in_quotes = !in_quotes;
+        } else if b == separator && !in_quotes {
+            parts.push(trim_ows(&value[start..i]));
+            start = i + 1;
+        }
+    }
+    parts.push(trim_ows(&value[start..]));
+    parts
+}
+
+/// Returns the elements of a comma-separated list spread over any number of fields, skipping
+/// the empty ones the list syntax allows.
+fn list_elements<'a>(values: &[&'a [u8]]) -> Vec<&'a [u8]> {
+    values
+        .iter()
+        .flat_map(|value| split_unquoted(value, b','))
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+/// Parses a list of tokens, lower-casing them.
+fn parse_token_list(name: &[u8], values: &[&[u8]]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    list_elements(values)
+        .into_iter()
+        .map(|element| {
+            grammar::is_token(element)
+                .then(|| element.to_ascii_lowercase())
+                .ok_or_else(|| invalid(name, "bad token"))
+        })
+        .collect()
+}
+
+/// Parses a token or a quoted-string, returning the quoted-string's content unescaped.
+fn parse_word(value: &[u8]) -> Option<Vec<u8>> {
+    let Some(quoted) = value.strip_prefix(b"\"") else {
+        return grammar::is_token(value).then(|| value.to_vec());
+    };
+    let mut out = Vec::with_capacity(quoted.len());
+    let mut bytes = quoted.iter();
+    loop {
+        match *bytes.next()? {
+            b'"' => return bytes.as_slice().is_empty().then_some(out),
+            b'\\' => out.push(*bytes.next()?),
+            b => out.push(b),
+        }
+    }
+}
+
+/// Formats a value as a token if it is one, and as a quoted-string otherwise.
+fn format_word(value: &[u8], out: &mut Vec<u8>) {
+    if grammar::is_token(value) {
+        out.extend_from_slice(value);
+        return;
+    }
+    out.push(b'"');
+    for &b in value {
+        if b == b'"' || b == b'\\' {
+            out.push(b'\\');
+        }
+        out.push(b);
+    }
+    out.push(b'"');
+}
+
+/// Parses a lower-cased `type/subtype`.
+fn parse_media_type(value: &[u8]) -> Option<Vec<u8>> {
+    let slash = value.iter().position(|&b| b == b'/')?;
+    let valid = grammar::is_token(&value[..slash]) && grammar::is_token(&value[slash + 1..]);
+    valid.then(|| value.to_ascii_lowercase())
+}
+
+/// Parses the `name=value` parameters that follow a media type, already split on `;`.
+fn parse_params(parts: &[&[u8]]) -> Option<Vec<Param>> {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let eq = part.iter().position(|&b| b == b'=')?;
+            let name = trim_ows(&part[..eq]);
+            if !grammar::is_token(name) {
+                return None;
+            }
+            Some((name.to_ascii_lowercase(), parse_word(trim_ows(&part[eq + 1..]))?))
+        })
+        .collect()
+}
+
+/// Formats parameters as `; name=value` pairs.
+fn format_params(params: &[Param], out: &mut Vec<u8>) {
+    for (name, value) in params {
+        out.extend_from_slice(b"; ");
+        out.extend_from_slice(name);
+        out.push(b'=');
+        format_word(value, out);
+    }
+}
+
+/// Finds the value of a parameter, ignoring the case of `name`.
+fn find_param<'a>(params: &'a [Param], name: &[u8]) -> Option<&'a [u8]> {
+    params.iter().find(|(found, _)| found.eq_ignore_ascii_case(name)).map(|(_, value)| &value[..])
+}
+
+/// Parses a q-value, `0` to `1` with at most three decimals, into thousandths.
+fn parse_qvalue(value: &[u8]) -> Option<u16> {
+    let (whole, decimals) = match value.iter().position(|&b| b == b'.') {
+        Some(dot) => (&value[..dot], &value[dot + 1..]),
+        None => (value, &[][..]),
+    };
+    if decimals.len() > 3 || !decimals.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let mut quality = match whole {
+        b"0" => 0,
+        b"1" => 1000,
+        _ => return None,
+    };
+    for (digit, scale) in decimals.iter().zip([100, 10, 1]) {
+        quality += u16::from(digit - b'0') * scale;
+    }
+    (quality <= 1000).then_some(quality)
+}
+
+/// Formats thousandths as the shortest q-value that parses back to them.
+fn format_qvalue(quality: u16, out: &mut Vec<u8>) {
+    match quality {
+        0 => out.push(b'0'),
+        1000.. => out.push(b'1'),
+        _ => {
+            let decimals = format!("{:03}", quality);
+            out.extend_from_slice(b"0.");
+            out.extend_from_slice(decimals.trim_end_matches('0').as_bytes());
+        }
+    }
+}
+
+/// Parses a header holding a single HTTP-date.
+fn parse_date_field(name: &[u8], values: &[&[u8]], now: SystemTime) -> Result<SystemTime, ProtocolError> {
+    parse_http_date_at(single(name, values)?, now).map_err(|_| invalid(name, "bad HTTP-date"))
+}
+
+/// Parses `If-Match` or `If-None-Match` spread over any number of fields.
+fn parse_entity_tag_fields(name: &[u8], values: &[&[u8]]) -> Result<EntityTags, ProtocolError> {
+    let mut tags = Vec::new();
+    for value in values {
+        match parse_entity_tags(value) {
+            Some(EntityTags::Any) if values.len() == 1 => return Ok(EntityTags::Any),
+            Some(EntityTags::Tags(more)) => tags.extend(more),
+            _ => return Err(invalid(name, "bad entity tag list")),
+        }
+    }
+    Ok(EntityTags::Tags(tags))
+}
+
+/// Parses `*` or a comma-separated list of entity tags.
+///
+/// The list is walked tag by tag rather than split on commas first, since a tag may contain
+/// commas and backslashes with no special meaning.
+fn parse_entity_tags(value: &[u8]) -> Option<EntityTags> {
+    if trim_ows(value) == b"*" {
+        return Some(EntityTags::Any);
+    }
+    let mut tags = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = trim_ows(rest);
+        match rest {
+            [] => return Some(EntityTags::Tags(tags)),
+            [b',', after @ ..] => {
+                rest = after;
+                continue;
+            }
+            _ => {}
+        }
+        let (weak, after) = match rest.strip_prefix(b"W/") {
+            Some(after) => (true, after),
+            None => (false, rest),
+        };
+        let quoted = after.strip_prefix(b"\"")?;
+        let end = quoted.iter().position(|&b| b == b'"')?;
+        let tag = &quoted[..end];
+        // etagc is any visible byte but DQUOTE, obs-text included
+        if !tag.iter().all(|&b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80) {
+            return None;
+        }
+        tags.push(EntityTag { weak, tag: tag.to_vec() });
+        rest = trim_ows(&quoted[end + 1..]);
+        if !rest.is_empty() && !rest.starts_with(b",") {
+            return None;
+        }
+    }
+}
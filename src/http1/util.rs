@@ -1,43 +1,134 @@
-use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
-use regex::Regex;
 
-#[derive(Debug)]
+/// What kind of protocol violation a [`ProtocolError`] reports, so callers can react to it
+/// without matching on the message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The request line didn't match the grammar.
+    BadRequestLine,
+    /// The status line didn't match the grammar.
+    BadStatusLine,
+    /// A status code outside the range allowed for the event.
+    BadStatusCode,
+    /// An HTTP version we can't speak, or one that doesn't parse.
+    BadHttpVersion,
+    /// A header field with an illegal name, value or line shape.
+    BadHeader,
+    /// An HTTP/1.1 request without a `Host` header.
+    MissingHost,
+    /// More than one `Host` header.
+    DuplicateHost,
+    /// A `Content-Length` that isn't a plain decimal number.
+    BadContentLength,
+    /// `Content-Length` values that disagree with each other.
+    ConflictingContentLength,
+    /// A `Transfer-Encoding` that can't frame the message body.
+    UnsupportedTransferEncoding,
+    /// Framing headers that two HTTP implementations could read differently, the raw material
+    /// of request smuggling.
+    AmbiguousFraming,
+    /// A message head or trailer block bigger than the configured limit.
+    OversizedHead,
+    /// A chunk-size line bigger than the configured limit.
+    OversizedChunkLine,
+    /// A malformed chunk in a chunked body.
+    BadChunk,
+    /// A body that didn't match its declared length.
+    BodyLengthMismatch,
+    /// Trailer fields where the framing can't carry them.
+    TrailersNotAllowed,
+    /// The peer closed the connection in the middle of a message.
+    IncompleteMessage,
+    /// Bytes arrived when nothing more was expected.
+    UnexpectedData,
+    /// An event that isn't allowed in the current state.
+    IllegalEvent,
+    /// The connection already failed and can't be used any more.
+    ErrorState,
+}
+
+/// The single error type used across the crate.
+///
+/// `LocalProtocolError` means our side (usually the application calling `send`) broke the
+/// protocol; `RemoteProtocolError` means the peer did. Both carry a machine-readable
+/// [`ErrorKind`], an HTTP status code a server could answer with, and, when known, the byte
+/// offset in the stream at which the problem was found.
+///
+/// # Example
+///
+/// ```rust
+/// use yo::http1::util::{ErrorKind, ProtocolError};
+///
+/// let error = ProtocolError::new_local(ErrorKind::MissingHost, "Missing mandatory Host: header", 400);
+/// assert!(error.is_local());
+///
+/// // Errors found while parsing the peer's data are reported as remote ones
+/// let error = error.into_remote().with_offset(42);
+/// assert!(error.is_remote());
+/// assert_eq!(error.kind(), ErrorKind::MissingHost);
+/// assert_eq!(error.error_status_hint(), 400);
+/// assert_eq!(error.offset(), Some(42));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProtocolError {
-    LocalProtocolError { message: String, error_status_hint: u16 },
-    RemoteProtocolError { message: String, error_status_hint: u16 },
+    LocalProtocolError {
+        kind: ErrorKind,
+        message: String,
+        error_status_hint: u16,
+        offset: Option<usize>,
+    },
+    RemoteProtocolError {
+        kind: ErrorKind,
+        message: String,
+        error_status_hint: u16,
+        offset: Option<usize>,
+    },
 }
 
 impl ProtocolError {
     /// Creates a new local protocol error.
     ///
     /// # Arguments
+    /// * `kind` - What went wrong.
     /// * `message` - Error message.
     /// * `error_status_hint` - Error status code.
     ///
     /// # Returns
     /// A `ProtocolError::LocalProtocolError` variant.
-    pub fn new_local(message: &str, error_status_hint: u16) -> Self {
+    pub fn new_local(kind: ErrorKind, message: impl Into<String>, error_status_hint: u16) -> Self {
         ProtocolError::LocalProtocolError {
-            message: message.to_string(),
+            kind,
+            message: message.into(),
             error_status_hint,
+            offset: None,
         }
     }
     /// Creates a new remote protocol error.
     ///
     /// # Arguments
+    /// * `kind` - What went wrong.
     /// * `message` - Error message.
     /// * `error_status_hint` - Error status code.
     ///
     /// # Returns
     /// A `ProtocolError::RemoteProtocolError` variant.
-    pub fn new_remote(message: &str, error_status_hint: u16) -> Self {
+    pub fn new_remote(kind: ErrorKind, message: impl Into<String>, error_status_hint: u16) -> Self {
         ProtocolError::RemoteProtocolError {
-            message: message.to_string(),
+            kind,
+            message: message.into(),
             error_status_hint,
+            offset: None,
         }
     }
+    /// Creates a local protocol error with the default 400 status hint.
+    pub fn local(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::new_local(kind, message, 400)
+    }
+    /// Creates a remote protocol error with the default 400 status hint.
+    pub fn remote(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::new_remote(kind, message, 400)
+    }
     /// Retrieves the error status hint.
     ///
     /// # Returns
@@ -48,64 +139,79 @@ impl ProtocolError {
             ProtocolError::RemoteProtocolError { error_status_hint, .. } => *error_status_hint,
         }
     }
+    /// Retrieves the machine-readable kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ProtocolError::LocalProtocolError { kind, .. } => *kind,
+            ProtocolError::RemoteProtocolError { kind, .. } => *kind,
+        }
+    }
+    /// Retrieves the human-readable message.
+    pub fn message(&self) -> &str {
+        match self {
+            ProtocolError::LocalProtocolError { message, .. } => message,
+            ProtocolError::RemoteProtocolError { message, .. } => message,
+        }
+    }
+    /// Retrieves the byte offset in the stream at which the error was found, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ProtocolError::LocalProtocolError { offset, .. } => *offset,
+            ProtocolError::RemoteProtocolError { offset, .. } => *offset,
+        }
+    }
+    /// Whether our side broke the protocol.
+    pub fn is_local(&self) -> bool {
+        matches!(self, ProtocolError::LocalProtocolError { .. })
+    }
+    /// Whether the peer broke the protocol.
+    pub fn is_remote(&self) -> bool {
+        matches!(self, ProtocolError::RemoteProtocolError { .. })
+    }
+    /// Records where in the stream the error was found, unless a more precise offset was
+    /// already attached.
+    pub fn with_offset(mut self, at: usize) -> Self {
+        match &mut self {
+            ProtocolError::LocalProtocolError { offset, .. }
+            | ProtocolError::RemoteProtocolError { offset, .. } => {
+                offset.get_or_insert(at);
+            }
+        }
+        self
+    }
+    /// Re-labels the error as the peer's fault. Validation code reports everything as local;
+    /// when it runs over data we received, the blame belongs to the peer.
+    pub fn into_remote(self) -> Self {
+        match self {
+            ProtocolError::LocalProtocolError { kind, message, error_status_hint, offset } => {
+                ProtocolError::RemoteProtocolError { kind, message, error_status_hint, offset }
+            }
+            remote => remote,
+        }
+    }
 }
 
 impl fmt::Display for ProtocolError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ProtocolError::LocalProtocolError { message, .. } => write!(f, "Local error: {}", message),
-            ProtocolError::RemoteProtocolError { message, .. } => write!(f, "Remote error: {}", message),
+            ProtocolError::LocalProtocolError { message, .. } => write!(f, "Local error: {}", message)?,
+            ProtocolError::RemoteProtocolError { message, .. } => write!(f, "Remote error: {}", message)?,
+        }
+        if let Some(offset) = self.offset() {
+            write!(f, " (at byte {})", offset)?;
         }
+        Ok(())
     }
 }
 
 impl std::error::Error for ProtocolError {}
 
-/// Validates data against a regex pattern, returning captured groups as a hashmap.
-///
-/// # Arguments
-/// * `regex` - The regex to validate against.
-/// * `data` - The data to validate.
-/// * `msg` - The error message to use if validation fails.
-/// * `format_args` - Arguments to format into the message.
-///
-/// # Returns
-/// `Ok(HashMap)` with captured group names and values on success, or a `ProtocolError` on failure.
-pub fn validate(
-    regex: &Regex,
-    data: &[u8],
-    msg: &str,
-    format_args: &[String],
-) -> Result<HashMap<String, String>, ProtocolError> {
-    
-    if let Ok(data_str) = std::str::from_utf8(data) {  
-        if let Some(captures) = regex.captures(data_str) {  
-            let mut group_dict = HashMap::new();
-            
-            
-            for name in regex.capture_names() {  
-                if let Some(name) = name {  
-                    if let Some(value) = captures.name(name) {  
-                        group_dict.insert(name.to_string(), value.as_str().to_string());
-                    }
-                }
-            }
-            Ok(group_dict)
-        } else {
-            let formatted_msg = if !format_args.is_empty() {
-                format!("{} {}", msg, format_args.join(", "))
-            } else {
-                msg.to_string()
-            };
-            Err(ProtocolError::new_local(&formatted_msg, 400))
-        }
-    } else {
-        
-        Err(ProtocolError::new_local("Invalid UTF-8 input", 400))  
+impl From<ProtocolError> for std::io::Error {
+    fn from(err: ProtocolError) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
     }
 }
 
-
 /// Sentinel trait for types that act as sentinels.
 ///
 /// This trait can be implemented by types that are used to signal specific conditions.
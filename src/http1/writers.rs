@@ -1,79 +1,23 @@
 use std::collections::HashMap;
-use std::io::Result;
-use std::fmt::Debug;
-use std::io;
-
-// Define the Event enum, which can contain either Data or EndOfMessage (EOM) events
-#[derive(Debug)]
-pub enum Event {
-    Data(Vec<u8>),
-    EndOfMessage(Headers),
-    // Add more variants as needed
-}
-
-// Define Headers struct, which holds a list of headers as tuples
-#[derive(Debug)]
-pub struct Headers {
-    pub full_items: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>,
-}
-
-impl Headers {
-    // Creates a new, empty `Headers` instance
-    pub fn new() -> Self {
-        Headers { full_items: vec![] }
-    }
-    
-    // Adds a new header to the collection
-    pub fn add(&mut self, raw_name: Vec<u8>, name: Vec<u8>, value: Vec<u8>) {
-        self.full_items.push((raw_name, name, value));
-    }
-}
-
-// Define the Request struct, which represents an HTTP request
-#[derive(Debug)]
-pub struct Request {
-    pub method: Vec<u8>,
-    pub target: Vec<u8>,
-    pub http_version: Vec<u8>,
-    pub headers: Headers,
-}
-
-// Define the Response struct, which represents an HTTP response
-#[derive(Debug)]
-pub struct Response {
-    pub status_code: u16,
-    pub reason: Vec<u8>,
-    pub http_version: Vec<u8>,
-    pub headers: Headers,
-}
 
-// Define the InformationalResponse struct, for HTTP 1xx responses
-#[derive(Debug)]
-pub struct InformationalResponse {
-    pub status_code: u16,
-    pub reason: Vec<u8>,
-    pub http_version: Vec<u8>,
-    pub headers: Headers,
-}
+use lazy_static::lazy_static;
 
-// Define a Writer type alias that abstracts away the writing mechanism
-pub type Writer = Box<dyn Fn(&[u8]) -> Result<()> + Send + Sync>;
+use super::events::{Event, Request};
+use super::framing::Framing;
+use super::headers::Headers;
+use super::state::Sentinel;
+use super::util::{ErrorKind, ProtocolError};
 
-// Define a custom error type for protocol errors
-#[derive(Debug)]
-pub struct LocalProtocolError(pub String);
+// Writers append their output to a byte buffer that the connection hands back to the caller
+pub type Writer = Vec<u8>;
 
-// Implement the From trait to convert LocalProtocolError into io::Error
-impl From<LocalProtocolError> for io::Error {
-    fn from(err: LocalProtocolError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, err.0)
-    }
-}
+// Everything a writer rejects is a mistake on our side of the connection
+type Result<T> = std::result::Result<T, ProtocolError>;
 
 // Trait for writing bodies, e.g., in HTTP responses or requests
 pub trait BodyWriter {
-    fn send_data(&mut self, data: &[u8], write: &Writer) -> Result<()>;
-    fn send_eom(&mut self, headers: Headers, write: &Writer) -> Result<()>;
+    fn send_data(&mut self, data: &[u8], write: &mut Writer) -> Result<()>;
+    fn send_eom(&mut self, headers: Headers, write: &mut Writer) -> Result<()>;
 }
 
 // Writer for Content-Length encoding
@@ -88,21 +32,22 @@ impl ContentLengthWriter {
 }
 
 impl BodyWriter for ContentLengthWriter {
-    fn send_data(&mut self, data: &[u8], write: &Writer) -> Result<()> {
+    fn send_data(&mut self, data: &[u8], write: &mut Writer) -> Result<()> {
         if let Some(new_length) = self.length.checked_sub(data.len()) {
             self.length = new_length;
         } else {
-            return Err(LocalProtocolError("Too much data for declared Content-Length".to_string()).into());
+            return Err(ProtocolError::local(ErrorKind::BodyLengthMismatch, "Too much data for declared Content-Length"));
         }
-        write(data)
+        write.extend_from_slice(data);
+        Ok(())
     }
 
-    fn send_eom(&mut self, headers: Headers, write: &Writer) -> Result<()> {
+    fn send_eom(&mut self, headers: Headers, write: &mut Writer) -> Result<()> {
         if self.length != 0 {
-            return Err(LocalProtocolError("Too little data for declared Content-Length".to_string()).into());
+            return Err(ProtocolError::local(ErrorKind::BodyLengthMismatch, "Too little data for declared Content-Length"));
         }
-        if !headers.full_items.is_empty() {
-            return Err(LocalProtocolError("Content-Length and trailers don't mix".to_string()).into());
+        if !headers.full_items().is_empty() {
+            return Err(ProtocolError::local(ErrorKind::TrailersNotAllowed, "Content-Length and trailers don't mix"));
         }
         Ok(())
     }
@@ -112,20 +57,20 @@ impl BodyWriter for ContentLengthWriter {
 pub struct ChunkedWriter;
 
 impl BodyWriter for ChunkedWriter {
-    fn send_data(&mut self, data: &[u8], write: &Writer) -> Result<()> {
+    fn send_data(&mut self, data: &[u8], write: &mut Writer) -> Result<()> {
+        // A zero-length chunk would be read as the end of the body
         if data.is_empty() {
             return Ok(());
         }
-        write(format!("{:x}\r\n", data.len()).as_bytes())?;
-        write(data)?;
-        write(b"\r\n")?;
+        write.extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+        write.extend_from_slice(data);
+        write.extend_from_slice(b"\r\n");
         Ok(())
     }
 
-    fn send_eom(&mut self, headers: Headers, write: &Writer) -> Result<()> {
-        write(b"0\r\n")?;
-        write(b"\r\n")?;
-        Ok(())
+    fn send_eom(&mut self, headers: Headers, write: &mut Writer) -> Result<()> {
+        write.extend_from_slice(b"0\r\n");
+        write_headers(&headers, write)
     }
 }
 
@@ -133,121 +78,94 @@ impl BodyWriter for ChunkedWriter {
 pub struct Http10Writer;
 
 impl BodyWriter for Http10Writer {
-    fn send_data(&mut self, data: &[u8], write: &Writer) -> Result<()> {
-        write(data)?;
+    fn send_data(&mut self, data: &[u8], write: &mut Writer) -> Result<()> {
+        write.extend_from_slice(data);
         Ok(())
     }
 
-    fn send_eom(&mut self, headers: Headers, write: &Writer) -> Result<()> {
-        if !headers.full_items.is_empty() {
-            return Err(LocalProtocolError("can't send trailers to HTTP/1.0 client".to_string()).into());
+    fn send_eom(&mut self, headers: Headers, write: &mut Writer) -> Result<()> {
+        if !headers.full_items().is_empty() {
+            return Err(ProtocolError::local(ErrorKind::TrailersNotAllowed, "can't send trailers to HTTP/1.0 client"));
         }
         Ok(())
     }
 }
 
-// Define Sentinel enum, which represents different states in the protocol flow
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
-pub enum Sentinel {
-    Client,
-    Server,
-    Idle,
-    SendBody,
-    SendResponse,
-}
+// Head writers serialize the request or response head a role sends while in a given state
+pub type HeadWriter = fn(&Event, &mut Writer) -> Result<()>;
 
-// Writers type maps tuples of Sentinels to their corresponding writer functions
-type Writers = HashMap<(Sentinel, Sentinel), Box<dyn Fn(Event, Writer) -> Result<()> + Send + Sync>>;
+// Writers type maps (role, state) pairs of state::Sentinels to their head writers; body writers
+// come from the framing decision instead, see body_writer
+pub type Writers = HashMap<(Sentinel, Sentinel), HeadWriter>;
 
 // Function to write HTTP headers
-pub fn write_headers(headers: &Headers, write: &Writer) -> Result<()> {
-    for (raw_name, name, value) in &headers.full_items {
-        if name == b"host" {
-            write(format!("{}: {}\r\n", String::from_utf8_lossy(raw_name), String::from_utf8_lossy(value)).as_bytes())?;
-        }
-    }
-    for (raw_name, name, value) in &headers.full_items {
-        if name != b"host" {
-            write(format!("{}: {}\r\n", String::from_utf8_lossy(raw_name), String::from_utf8_lossy(value)).as_bytes())?;
-        }
+pub fn write_headers(headers: &Headers, write: &mut Writer) -> Result<()> {
+    // Host goes first, as RFC 9110 asks of clients
+    let host_first = headers.full_items().iter().filter(|(_, name, _)| &name[..] == b"host");
+    let others = headers.full_items().iter().filter(|(_, name, _)| &name[..] != b"host");
+    for (raw_name, _, value) in host_first.chain(others) {
+        write.extend_from_slice(raw_name);
+        write.extend_from_slice(b": ");
+        write.extend_from_slice(value);
+        write.extend_from_slice(b"\r\n");
     }
-    write(b"\r\n")?;
+    write.extend_from_slice(b"\r\n");
     Ok(())
 }
 
 // Function to write HTTP request to the writer
-pub fn write_request(request: &Request, write: &Writer) -> Result<()> {
+pub fn write_request(request: &Request, write: &mut Writer) -> Result<()> {
     if request.http_version != b"1.1" {
-        return Err(LocalProtocolError("I only send HTTP/1.1".to_string()).into());
+        return Err(ProtocolError::local(ErrorKind::BadHttpVersion, "I only send HTTP/1.1"));
     }
-    write(format!("{} {} HTTP/1.1\r\n", String::from_utf8_lossy(&request.method), String::from_utf8_lossy(&request.target)).as_bytes())?;
+    write.extend_from_slice(&request.method);
+    write.extend_from_slice(b" ");
+    write.extend_from_slice(&request.target);
+    write.extend_from_slice(b" HTTP/1.1\r\n");
     write_headers(&request.headers, write)
 }
 
-// Function to write HTTP response to the writer
-pub fn write_any_response(response: &Response, write: &Writer) -> Result<()> {
-    if response.http_version != b"1.1" {
-        return Err(LocalProtocolError("I only send HTTP/1.1".to_string()).into());
+// Function to write a final or informational response head to the writer
+pub fn write_any_response(response: &Event, write: &mut Writer) -> Result<()> {
+    let (status_code, reason, headers, http_version) = match response {
+        Event::Response(r) => (r.status_code, &r.reason, &r.headers, &r.http_version),
+        Event::InformationalResponse(r) => (r.status_code, &r.reason, &r.headers, &r.http_version),
+        _ => return Err(ProtocolError::local(ErrorKind::IllegalEvent, "not a response event")),
+    };
+    if http_version != b"1.1" {
+        return Err(ProtocolError::local(ErrorKind::BadHttpVersion, "I only send HTTP/1.1"));
     }
-    write(format!("HTTP/1.1 {} {}\r\n", response.status_code, String::from_utf8_lossy(&response.reason)).as_bytes())?;
-    write_headers(&response.headers, write)
+    write.extend_from_slice(format!("HTTP/1.1 {} ", status_code).as_bytes());
+    write.extend_from_slice(reason);
+    write.extend_from_slice(b"\r\n");
+    write_headers(headers, write)
 }
 
 // Function to create writers for different protocol states
 pub fn create_writers() -> Writers {
     let mut writers: Writers = HashMap::new();
 
-    // The closure signature has been modified to ensure it matches the expected `Box<dyn Fn(Event, Writer) -> Result<()> + Send + Sync>`
-    writers.insert(
-        (Sentinel::Client, Sentinel::Idle),
-        Box::new(|event: Event, write: Writer| {
-            match event {
-                Event::Data(data) => {
-                    if let Some(request) = parse_request(&data) {
-                        if request.http_version != b"1.1" {
-                            return Err(LocalProtocolError("I only send HTTP/1.1".to_string()).into());
-                        }
-                        write(format!("{} {} HTTP/1.1\r\n", String::from_utf8_lossy(&request.method), String::from_utf8_lossy(&request.target)).as_bytes())?;
-                        write_headers(&request.headers, &write)?;
-                    } else {
-                        return Err(LocalProtocolError("Invalid request data".to_string()).into());
-                    }
-                }
-                Event::EndOfMessage(headers) => {
-                    write_headers(&headers, &write)?;
-                }
-            }
-            Ok(())
-        }) as Box<dyn Fn(Event, Writer) -> Result<()> + Send + Sync>,
-    );
-
-    writers.insert(
-        (Sentinel::Server, Sentinel::Idle),
-        Box::new(|event: Event, write: Writer| {
-            match event {
-                Event::Data(data) => {
-                    // Handle server-side data
-                }
-                Event::EndOfMessage(headers) => {
-                    write_headers(&headers, &write)?;
-                }
-            }
-            Ok(())
-        }) as Box<dyn Fn(Event, Writer) -> Result<()> + Send + Sync>,
-    );
-
-    // Add more writers for other states as needed
+    writers.insert((Sentinel::Client, Sentinel::Idle), |event, write| match event {
+        Event::Request(request) => write_request(request, write),
+        _ => Err(ProtocolError::local(ErrorKind::IllegalEvent, "Invalid request data")),
+    });
+    writers.insert((Sentinel::Server, Sentinel::Idle), write_any_response);
+    writers.insert((Sentinel::Server, Sentinel::SendResponse), write_any_response);
 
     writers
 }
 
-// Dummy function to simulate parsing request data (you can replace this with actual parsing logic)
-fn parse_request(data: &[u8]) -> Option<Request> {
-    Some(Request {
-        method: b"GET".to_vec(),
-        target: b"/index.html".to_vec(),
-        http_version: b"1.1".to_vec(),
-        headers: Headers::new(),
-    })
+lazy_static! {
+    // The head writers, built once
+    pub static ref WRITERS: Writers = create_writers();
 }
 
+// Function to create the body writer for a body framed as `framing`
+pub fn body_writer(framing: Framing) -> Box<dyn BodyWriter> {
+    match framing {
+        Framing::NoBody => Box::new(ContentLengthWriter::new(0)),
+        Framing::ContentLength(length) => Box::new(ContentLengthWriter::new(length)),
+        Framing::Chunked => Box::new(ChunkedWriter),
+        Framing::ReadUntilClose => Box::new(Http10Writer),
+    }
+}
@@ -2,14 +2,28 @@
 #![allow(dead_code)]
 pub mod http1 {
     pub mod connection;
+    pub mod date;
+    pub mod grammar;
+    #[cfg(feature = "regex")]
     pub mod http_regex;
+    pub mod scanner;
     pub mod recieve_buffer;
     pub mod version;
     pub mod events;
+    pub mod framing;
     pub mod state;
     pub mod writers;
     pub mod headers;
     pub mod readers;
+    pub mod typed_headers;
     pub mod util;
+
+    pub use connection::{Connection, NextEvent, Role, Sentinel, DEFAULT_MAX_INCOMPLETE_EVENT_SIZE};
+    pub use events::{
+        ConnectionClosed, Data, EndOfMessage, Event, InformationalResponse, Payload, Request,
+        Response,
+    };
+    pub use headers::Headers;
+    pub use util::{ErrorKind, ProtocolError};
 }
 